@@ -0,0 +1,66 @@
+use std::process::{Command, Stdio};
+
+use cmus_notify::{CmusClient, ConnectionConfig, Error};
+
+/// Playback controls shown on the notification, as (cmus command, label).
+///
+/// The command is used as the action identifier. Only D-Bus notifications have buttons.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub const ACTIONS: &[(&str, &str)] = &[
+    ("player-prev", "Previous"),
+    ("player-pause", "Play/Pause"),
+    ("player-next", "Next"),
+    ("player-stop", "Stop"),
+];
+
+/// Map an action invoked on the notification to its cmus command.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub fn get_command(action: &str) -> Option<&'static str> {
+    ACTIONS
+        .iter()
+        .find(|(command, _)| *command == action)
+        .map(|(command, _)| *command)
+}
+
+/// Send a command to cmus over a new connection and wait for it to be processed.
+pub fn send_command(command: &str, config: &ConnectionConfig) -> Result<(), Error> {
+    CmusClient::connect(config)?.command(command)?;
+
+    Ok(())
+}
+
+/// End the process `pid` waiting for the buttons of a previous notification.
+pub fn end_waiter(pid: u32) {
+    let _ = Command::new("kill")
+        .arg(pid.to_string())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
+
+#[cfg(test)]
+mod test_actions {
+    use super::{end_waiter, get_command};
+    use rstest::rstest;
+    use std::process::Command;
+
+    #[rstest]
+    #[case("player-prev", Some("player-prev"))]
+    #[case("player-pause", Some("player-pause"))]
+    #[case("player-next", Some("player-next"))]
+    #[case("player-stop", Some("player-stop"))]
+    #[case("__closed", None)]
+    #[case("quit", None)]
+    fn test_get_command(#[case] action: &str, #[case] expected: Option<&str>) {
+        assert_eq!(get_command(action), expected);
+    }
+
+    #[test]
+    fn test_end_waiter() {
+        let mut waiter = Command::new("sleep").arg("60").spawn().unwrap();
+
+        end_waiter(waiter.id());
+
+        assert!(!waiter.wait().unwrap().success());
+    }
+}
@@ -0,0 +1,256 @@
+use std::sync::OnceLock;
+
+use crate::notifier::Message;
+
+/// What the notification server can show, from `GetCapabilities` and `GetServerInformation`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capabilities {
+    pub body: bool,
+    pub body_markup: bool,
+    pub actions: bool,
+    /// The icon of the notification is shown.
+    pub icons: bool,
+    /// Notifications are kept until dismissed, unless they are transient.
+    pub persistence: bool,
+    /// Covers can be sent with the `image-path` hint, added in version 1.2 of the specification.
+    pub image_path: bool,
+}
+
+static CAPABILITIES: OnceLock<Capabilities> = OnceLock::new();
+
+/// Capabilities of the notification server, queried once per process.
+///
+/// When the server cannot be queried, the result is not cached and everything but the actions is
+/// assumed to be supported.
+pub fn get() -> Capabilities {
+    if let Some(capabilities) = CAPABILITIES.get() {
+        return capabilities.clone();
+    }
+
+    match query() {
+        Ok(capabilities) => CAPABILITIES.get_or_init(|| capabilities).clone(),
+        Err(_) => Capabilities::unknown(),
+    }
+}
+
+fn query() -> Result<Capabilities, notify_rust::error::Error> {
+    let capabilities = notify_rust::get_capabilities()?;
+    let info = notify_rust::get_server_information()?;
+
+    Ok(Capabilities::new(&capabilities, &info.spec_version))
+}
+
+impl Capabilities {
+    pub fn new(capabilities: &[String], spec_version: &str) -> Capabilities {
+        let has = |name: &str| capabilities.iter().any(|c| c == name);
+
+        Capabilities {
+            body: has("body"),
+            body_markup: has("body-markup"),
+            actions: has("actions"),
+            icons: has("icon-static") || has("icon-multi"),
+            persistence: has("persistence"),
+            image_path: parse_version(spec_version) >= (1, 2),
+        }
+    }
+
+    fn unknown() -> Capabilities {
+        Capabilities {
+            body: true,
+            body_markup: true,
+            actions: false,
+            icons: true,
+            persistence: true,
+            image_path: false,
+        }
+    }
+
+    /// The summary and body of the message as the server can show them.
+    ///
+    /// Markup is stripped if unsupported. Without icons, the status is added to the summary when
+    /// it is not already in the text. Without a body, the body is appended to the summary.
+    pub fn adapt(&self, message: &Message) -> (String, String) {
+        let mut summary = message.summary.to_string();
+        let mut body = if self.body_markup {
+            message.body.to_string()
+        } else {
+            strip_markup(message.body)
+        };
+
+        let status = message.status;
+        if !self.icons && !status.is_empty() && !summary.contains(status) && !body.contains(status)
+        {
+            summary.push_str(status);
+        }
+
+        if !self.body {
+            for line in body.lines().filter(|l| !l.is_empty()) {
+                summary.push_str(" | ");
+                summary.push_str(line);
+            }
+            body.clear();
+        }
+
+        (summary, body)
+    }
+}
+
+/// `major.minor` of a version, `(0, 0)` if invalid.
+fn parse_version(version: &str) -> (u32, u32) {
+    let mut parts = version.trim().split('.').map(|p| p.parse().ok());
+
+    match (parts.next().flatten(), parts.next().flatten()) {
+        (Some(major), Some(minor)) => (major, minor),
+        (Some(major), None) => (major, 0),
+        _ => (0, 0),
+    }
+}
+
+/// Remove the tags of the notification markup (`b`, `i`, `u`, `a` and `img`) and decode the
+/// entities. Anything else that looks like a tag is kept.
+fn strip_markup(body: &str) -> String {
+    let mut text = String::new();
+    let mut rest = body;
+
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        match rest.find('>').filter(|&end| is_markup_tag(&rest[1..end])) {
+            Some(end) => rest = &rest[end + 1..],
+            None => {
+                text.push('<');
+                rest = &rest[1..];
+            }
+        }
+    }
+    text.push_str(rest);
+
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn is_markup_tag(tag: &str) -> bool {
+    let tag = tag.strip_prefix('/').unwrap_or(tag);
+    let name = tag.split([' ', '/']).next().unwrap_or_default();
+
+    matches!(
+        name.to_ascii_lowercase().as_str(),
+        "b" | "i" | "u" | "a" | "img"
+    )
+}
+
+#[cfg(test)]
+mod test_capabilities {
+    use super::{parse_version, strip_markup, Capabilities};
+    use crate::notifier::Message;
+    use rstest::rstest;
+
+    const MESSAGE: Message = Message {
+        summary: "Metallideth - Orgasmatron",
+        body: "<b>Album</b> [Paused]\ntrack 3",
+        icon: "applications-multimedia",
+        cover: None,
+        status: " [Paused]",
+        replaces_id: None,
+        actions: false,
+    };
+
+    fn capabilities(names: &[&str]) -> Capabilities {
+        let names: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+
+        Capabilities::new(&names, "1.2")
+    }
+
+    #[test]
+    fn test_new() {
+        let names = [
+            "actions",
+            "body",
+            "body-markup",
+            "icon-static",
+            "persistence",
+        ];
+        let names: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+
+        assert_eq!(
+            Capabilities::new(&names, "1.2"),
+            Capabilities {
+                body: true,
+                body_markup: true,
+                actions: true,
+                icons: true,
+                persistence: true,
+                image_path: true,
+            }
+        );
+        assert!(!Capabilities::new(&names, "1.1").image_path);
+        assert!(!Capabilities::new(&[], "1.2").actions);
+    }
+
+    #[rstest]
+    #[case("1.2", (1, 2))]
+    #[case("1.10", (1, 10))]
+    #[case("2", (2, 0))]
+    #[case("", (0, 0))]
+    #[case("unknown", (0, 0))]
+    fn test_parse_version(#[case] version: &str, #[case] expected: (u32, u32)) {
+        assert_eq!(parse_version(version), expected);
+    }
+
+    #[rstest]
+    #[case("<b>bold</b> and <i>italic</i>", "bold and italic")]
+    #[case("<a href=\"https://cmus.github.io\">cmus</a>", "cmus")]
+    #[case("<img src=\"cover.jpg\" alt=\"cover\"/>", "")]
+    #[case("AC&amp;DC &lt;3", "AC&DC <3")]
+    #[case("1 < 2 > 0", "1 < 2 > 0")]
+    #[case("<3 <span>x</span>", "<3 <span>x</span>")]
+    #[case("unclosed <b", "unclosed <b")]
+    fn test_strip_markup(#[case] body: &str, #[case] expected: &str) {
+        assert_eq!(strip_markup(body), expected);
+    }
+
+    #[test]
+    fn test_adapt_full() {
+        let (summary, body) = capabilities(&["body", "body-markup", "icon-static"]).adapt(&MESSAGE);
+
+        assert_eq!(summary, MESSAGE.summary);
+        assert_eq!(body, MESSAGE.body);
+    }
+
+    #[test]
+    fn test_adapt_no_markup() {
+        let (summary, body) = capabilities(&["body", "icon-static"]).adapt(&MESSAGE);
+
+        assert_eq!(summary, MESSAGE.summary);
+        assert_eq!(body, "Album [Paused]\ntrack 3");
+    }
+
+    #[test]
+    fn test_adapt_no_icons() {
+        let message = Message {
+            body: "Album\ntrack 3",
+            ..MESSAGE
+        };
+        let (summary, _) = capabilities(&["body"]).adapt(&message);
+        assert_eq!(summary, "Metallideth - Orgasmatron [Paused]");
+
+        // Already in the body.
+        let (summary, _) = capabilities(&["body"]).adapt(&MESSAGE);
+        assert_eq!(summary, MESSAGE.summary);
+    }
+
+    #[test]
+    fn test_adapt_no_body() {
+        let (summary, body) = capabilities(&["icon-static"]).adapt(&MESSAGE);
+
+        assert_eq!(
+            summary,
+            "Metallideth - Orgasmatron | Album [Paused] | track 3"
+        );
+        assert_eq!(body, "");
+    }
+}
@@ -0,0 +1,437 @@
+//! Connection to a running cmus.
+
+use std::io;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::error::Error;
+use crate::metadata::{self, Metadata};
+use crate::protocol;
+use crate::socket;
+
+/// Where cmus listens and the timeouts of the connection, in milliseconds.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConnectionConfig {
+    /// Like `cmus-remote --server`: path of a Unix socket, or `host[:port]` of a cmus started
+    /// with `--listen`. The socket is discovered if neither `server` nor `socket` is set, see
+    /// [`socket::candidates`].
+    pub server: Option<String>,
+    /// Path of the Unix socket, even without a `/`. Ignored if `server` is set.
+    pub socket: Option<PathBuf>,
+    /// `server_password` of cmus, required over TCP.
+    pub password: Option<String>,
+    pub connect_timeout: u64,
+    pub read_timeout: u64,
+    pub write_timeout: u64,
+}
+
+impl ConnectionConfig {
+    /// Whether cmus runs on this machine: `server` is unset or a Unix socket, as decided by
+    /// [`CmusClient::connect`]. Paths reported by a remote cmus are not on this machine.
+    pub fn is_local(&self) -> bool {
+        self.server
+            .as_ref()
+            .is_none_or(|server| server.contains('/'))
+    }
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        ConnectionConfig {
+            server: None,
+            socket: None,
+            password: None,
+            connect_timeout: 1000,
+            read_timeout: 2000,
+            write_timeout: 2000,
+        }
+    }
+}
+
+enum Stream {
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Unix(s) => s.read(buf),
+            Stream::Tcp(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Unix(s) => s.write(buf),
+            Stream::Tcp(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Unix(s) => s.flush(),
+            Stream::Tcp(s) => s.flush(),
+        }
+    }
+}
+
+/// A connection to cmus, the same used by `cmus-remote`.
+///
+/// ```no_run
+/// use cmus_notify::{CmusClient, ConnectionConfig};
+///
+/// let mut client = CmusClient::connect(&ConnectionConfig::default())?;
+/// let (status, _errors) = client.status()?;
+/// println!("{} - {}", status.artist, status.title);
+///
+/// client.command("player-next")?;
+/// # Ok::<(), cmus_notify::Error>(())
+/// ```
+pub struct CmusClient {
+    stream: Stream,
+    /// Path of the socket or TCP address.
+    address: String,
+    /// Set until the first response after sending a password: cmus does not acknowledge the
+    /// password, it closes the connection when it is wrong.
+    unverified: bool,
+}
+
+impl CmusClient {
+    /// Connect to `config.server`, else to `config.socket`, else to the first socket of
+    /// [`socket::candidates`] where cmus answers.
+    ///
+    /// Like `cmus-remote`, a server containing a `/` is a Unix socket, anything else a TCP
+    /// address.
+    pub fn connect(config: &ConnectionConfig) -> Result<CmusClient, Error> {
+        match (&config.server, &config.socket) {
+            (Some(server), _) if server.contains('/') => {
+                CmusClient::connect_unix(Path::new(server), config)
+            }
+            (Some(server), _) => {
+                let password = config
+                    .password
+                    .as_deref()
+                    .ok_or_else(|| Error::PasswordRequired(server.clone()))?;
+                CmusClient::connect_tcp(server, password, config)
+            }
+            (None, Some(path)) => CmusClient::connect_unix(path, config),
+            (None, None) => CmusClient::discover(config),
+        }
+    }
+
+    /// Try each candidate socket in order.
+    ///
+    /// When none answers, the error is the one of the first socket that exists, or of the first
+    /// candidate if none exists.
+    fn discover(config: &ConnectionConfig) -> Result<CmusClient, Error> {
+        let mut error = None;
+        let mut found = false;
+
+        for candidate in socket::candidates() {
+            match CmusClient::connect_unix(&candidate.path, config) {
+                Ok(client) => return Ok(client),
+                Err(e) => {
+                    let exists = candidate.path.exists();
+                    if error.is_none() || (exists && !found) {
+                        error = Some(e);
+                        found = exists;
+                    }
+                }
+            }
+        }
+
+        Err(error.unwrap_or(Error::NoSocketPath))
+    }
+
+    /// Connect to the Unix socket of cmus at `path`.
+    pub fn connect_unix(path: &Path, config: &ConnectionConfig) -> Result<CmusClient, Error> {
+        Ok(CmusClient {
+            stream: Stream::Unix(protocol::connect(path, config)?),
+            address: path.display().to_string(),
+            unverified: false,
+        })
+    }
+
+    /// Connect to cmus started with `--listen address` and authenticate with its
+    /// `server_password`.
+    ///
+    /// `address` is `host:port`, or `host` for the default port 3000. A wrong password is only
+    /// detected by the first request, which fails with [`Error::Authentication`].
+    pub fn connect_tcp(
+        address: &str,
+        password: &str,
+        config: &ConnectionConfig,
+    ) -> Result<CmusClient, Error> {
+        let mut stream = Stream::Tcp(protocol::connect_tcp(address, config)?);
+        protocol::send(&mut stream, &format!("passwd {}\n", password))?;
+
+        Ok(CmusClient {
+            stream,
+            address: String::from(address),
+            unverified: true,
+        })
+    }
+
+    /// The path of the socket or the TCP address of cmus.
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Run a cmus command, e.g. `player-next` or `vol +10%`, and return its output.
+    ///
+    /// The output is empty for most commands.
+    pub fn command(&mut self, command: &str) -> Result<String, Error> {
+        if command.contains('\n') {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a command must be a single line",
+            )));
+        }
+
+        let response = protocol::send(&mut self.stream, &format!("{}\n", command))
+            .and_then(|_| protocol::recv(&mut self.stream));
+
+        match response {
+            Err(Error::Io(e)) if self.unverified && is_closed(&e) => Err(Error::Authentication),
+            Err(Error::Protocol(_)) if self.unverified => Err(Error::Authentication),
+            response => {
+                self.unverified = false;
+                response
+            }
+        }
+    }
+
+    /// Query the status of cmus.
+    ///
+    /// Invalid fields are left to their default value, their errors are returned with the status.
+    pub fn status(&mut self) -> Result<(Metadata, Vec<Error>), Error> {
+        let response = self.command("status")?;
+
+        if !response.lines().any(|l| l.starts_with("status ")) {
+            return Err(Error::Protocol(String::from("no status in response")));
+        }
+
+        Ok(metadata::parse(&response))
+    }
+}
+
+impl Drop for CmusClient {
+    fn drop(&mut self) {
+        let _ = match &self.stream {
+            Stream::Unix(s) => s.shutdown(Shutdown::Both),
+            Stream::Tcp(s) => s.shutdown(Shutdown::Both),
+        };
+    }
+}
+
+fn is_closed(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::UnexpectedEof | io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset
+    )
+}
+
+#[cfg(test)]
+mod test_client {
+    use super::{CmusClient, ConnectionConfig};
+    use crate::error::Error;
+    use crate::metadata::PlaybackStatus;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::os::unix::net::UnixListener;
+    use std::path::PathBuf;
+    use std::thread;
+    use std::time::Duration;
+
+    const STATUS: &str = "status playing\nfile /music/song.flac\nduration 258\nposition 12\n\
+                          tag artist Metallideth\ntag title Orgasmatron\nset repeat true\n\n";
+
+    /// Answer each line like cmus, the password is checked on the first line if set.
+    fn serve<S: std::io::Read + Write>(sock: S, password: Option<&str>) -> Vec<String> {
+        let mut reader = BufReader::new(sock);
+        let mut received = Vec::new();
+        let mut line = String::new();
+
+        if let Some(password) = password {
+            reader.read_line(&mut line).unwrap();
+            if line.trim_end() != format!("passwd {}", password) {
+                return received;
+            }
+        }
+
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                return received;
+            }
+
+            let line = line.trim_end();
+            let response = match line {
+                "status" => STATUS,
+                "pwd" => "/music\n\n",
+                _ => "\n",
+            };
+            received.push(String::from(line));
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+        }
+    }
+
+    fn fake_unix(name: &str) -> (PathBuf, thread::JoinHandle<Vec<String>>) {
+        let dir = std::env::temp_dir().join(format!(
+            "cmus-notify-client-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("socket");
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || serve(listener.accept().unwrap().0, None));
+
+        (path, server)
+    }
+
+    fn fake_tcp(password: &'static str) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || serve(listener.accept().unwrap().0, Some(password)));
+
+        (address, server)
+    }
+
+    #[test]
+    fn test_is_local() {
+        let config = |server: Option<&str>| ConnectionConfig {
+            server: server.map(String::from),
+            ..Default::default()
+        };
+
+        assert!(config(None).is_local());
+        assert!(config(Some("/run/user/1000/cmus-socket")).is_local());
+        assert!(!config(Some("music-box:3000")).is_local());
+    }
+
+    #[test]
+    fn test_unix() {
+        let (path, server) = fake_unix("unix");
+        let mut client = CmusClient::connect_unix(&path, &ConnectionConfig::default()).unwrap();
+
+        let (status, errors) = client.status().unwrap();
+        assert!(errors.is_empty());
+        assert_eq!(status.status, PlaybackStatus::Playing);
+        assert_eq!(status.artist, "Metallideth");
+        assert_eq!(status.position, Duration::from_secs(12));
+        assert!(status.settings.repeat);
+
+        assert_eq!(client.command("player-next").unwrap(), "");
+        assert_eq!(client.command("pwd").unwrap(), "/music");
+
+        drop(client);
+        assert_eq!(server.join().unwrap(), ["status", "player-next", "pwd"]);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_multiline_command() {
+        let (path, server) = fake_unix("multiline");
+        let mut client = CmusClient::connect_unix(&path, &ConnectionConfig::default()).unwrap();
+
+        assert!(matches!(client.command("status\nquit"), Err(Error::Io(_))));
+
+        drop(client);
+        assert!(server.join().unwrap().is_empty());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_tcp() {
+        let (address, server) = fake_tcp("secret");
+        let mut client =
+            CmusClient::connect_tcp(&address, "secret", &ConnectionConfig::default()).unwrap();
+
+        assert_eq!(client.status().unwrap().0.title, "Orgasmatron");
+        assert_eq!(client.command("player-pause").unwrap(), "");
+
+        drop(client);
+        assert_eq!(server.join().unwrap(), ["status", "player-pause"]);
+    }
+
+    #[test]
+    fn test_connect_server() {
+        let (address, server) = fake_tcp("secret");
+        let config = ConnectionConfig {
+            server: Some(address),
+            password: Some(String::from("secret")),
+            ..Default::default()
+        };
+
+        let mut client = CmusClient::connect(&config).unwrap();
+        assert_eq!(client.status().unwrap().0.artist, "Metallideth");
+
+        drop(client);
+        assert_eq!(server.join().unwrap(), ["status"]);
+
+        let (path, server) = fake_unix("server");
+        let config = ConnectionConfig {
+            server: Some(path.display().to_string()),
+            ..Default::default()
+        };
+
+        let mut client = CmusClient::connect(&config).unwrap();
+        assert_eq!(client.command("player-stop").unwrap(), "");
+
+        drop(client);
+        assert_eq!(server.join().unwrap(), ["player-stop"]);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_connect_socket() {
+        let (path, server) = fake_unix("socket");
+        let config = ConnectionConfig {
+            socket: Some(path.clone()),
+            ..Default::default()
+        };
+
+        let mut client = CmusClient::connect(&config).unwrap();
+        assert_eq!(client.address(), path.display().to_string());
+        assert_eq!(client.command("player-play").unwrap(), "");
+
+        drop(client);
+        assert_eq!(server.join().unwrap(), ["player-play"]);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_connect_server_without_password() {
+        let config = ConnectionConfig {
+            server: Some(String::from("127.0.0.1:3000")),
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            CmusClient::connect(&config),
+            Err(Error::PasswordRequired(address)) if address == "127.0.0.1:3000"
+        ));
+    }
+
+    #[test]
+    fn test_tcp_wrong_password() {
+        let (address, server) = fake_tcp("secret");
+        let mut client =
+            CmusClient::connect_tcp(&address, "guess", &ConnectionConfig::default()).unwrap();
+
+        assert!(matches!(client.status(), Err(Error::Authentication)));
+        assert!(server.join().unwrap().is_empty());
+    }
+}
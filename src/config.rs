@@ -0,0 +1,653 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use cmus_notify::cover::CoverConfig;
+use cmus_notify::{ConnectionConfig, PlaybackStatus};
+use serde::Deserialize;
+
+use crate::event::Event;
+use crate::template::Template;
+
+/// Overrides `connection.server`.
+pub const SERVER_ENV: &str = "CMUS_NOTIFY_SERVER";
+/// Overrides `connection.socket`.
+pub const SOCKET_ENV: &str = "CMUS_NOTIFY_SOCKET";
+/// Overrides `connection.password`.
+pub const PASSWORD_ENV: &str = "CMUS_NOTIFY_PASSWORD";
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub notification: NotificationConfig,
+    pub cover: CoverConfig,
+    pub format: FormatConfig,
+    pub connection: ConnectionConfig,
+    pub daemon: DaemonConfig,
+    pub errors: ErrorsConfig,
+    pub i3bar: I3barConfig,
+    pub stream: StreamConfig,
+    pub events: EventsConfig,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationConfig {
+    /// Summary used when the artist or the title is unknown.
+    pub default_title: String,
+    /// Icon used when no cover is found.
+    pub icon: String,
+    pub transient: bool,
+    /// Expiration timeout in milliseconds. Server default if not set.
+    pub timeout: Option<u32>,
+    /// Update the previous notification instead of showing a new one.
+    pub replace: bool,
+    /// Show playback control buttons when the server supports them.
+    pub actions: bool,
+    /// Where notifications are sent, in order. A failing backend does not stop the next ones.
+    pub backends: Vec<Backend>,
+    /// Program and arguments run by the `command` backend. `{summary}`, `{body}` and `{icon}`
+    /// are replaced in each argument.
+    pub command: Vec<String>,
+    /// File the `file` backend appends to.
+    pub file: Option<PathBuf>,
+}
+
+/// A destination of the notifications.
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// The notification server, over D-Bus on Linux.
+    Dbus,
+    /// An external program like `notify-send`.
+    Command,
+    Stdout,
+    Stderr,
+    /// One line per notification appended to `notification.file`.
+    File,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct FormatConfig {
+    /// Template of the notification summary.
+    pub summary: Template,
+    /// Template of the notification body.
+    pub body: Template,
+    /// Text of the `{status}` field.
+    pub status: StatusFormat,
+    /// Templates overriding `summary` and `body` for a given status.
+    pub playing: StatusTemplates,
+    pub paused: StatusTemplates,
+    pub stopped: StatusTemplates,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatusTemplates {
+    pub summary: Option<Template>,
+    pub body: Option<Template>,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatusFormat {
+    pub playing: String,
+    pub paused: String,
+    pub stopped: String,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct EventsConfig {
+    /// Events producing a notification, the others are ignored.
+    pub notify: Vec<Event>,
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        EventsConfig {
+            notify: vec![
+                Event::TrackChanged,
+                Event::Paused,
+                Event::Resumed,
+                Event::Stopped,
+            ],
+        }
+    }
+}
+
+/// Icons of internet radios, which have no cover.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StreamConfig {
+    /// Icon of every stream, `notification.icon` if unset.
+    pub icon: Option<String>,
+    /// Icon of a station, by station name or stream URL.
+    pub icons: BTreeMap<String, String>,
+}
+
+impl StreamConfig {
+    /// Icon of the stream `url` playing the station `station`.
+    pub fn icon(&self, station: &str, url: &str) -> Option<&str> {
+        self.icons
+            .get(station)
+            .or_else(|| self.icons.get(url))
+            .or(self.icon.as_ref())
+            .map(String::as_str)
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonConfig {
+    /// Delay between two status queries, in milliseconds.
+    pub interval: u64,
+    /// Delay between two connection attempts when cmus is not running, in milliseconds.
+    pub reconnect_interval: u64,
+}
+
+/// Colors of the i3bar block for each playback status, no color if empty.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct I3barConfig {
+    pub playing: String,
+    pub paused: String,
+    pub stopped: String,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ErrorsConfig {
+    pub policy: ErrorPolicy,
+}
+
+/// What to do when cmus cannot be queried or sends invalid data.
+#[derive(Debug, Default, Deserialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorPolicy {
+    /// Show the error in a notification.
+    Notify,
+    /// Only log the error, nothing is shown.
+    Log,
+    /// Log invalid fields and show the rest of the metadata, other errors are notified.
+    #[default]
+    Partial,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(PathBuf, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "Unable to read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "Invalid config {}: {}", path.display(), e),
+            ConfigError::Invalid(path, msg) => {
+                write!(f, "Invalid config {}: {}", path.display(), msg)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        NotificationConfig {
+            default_title: String::from("C* Music Player"),
+            icon: String::from("applications-multimedia"),
+            transient: true,
+            timeout: None,
+            replace: true,
+            actions: true,
+            backends: vec![Backend::Dbus],
+            command: ["notify-send", "--icon={icon}", "{summary}", "{body}"]
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect(),
+            file: None,
+        }
+    }
+}
+
+impl Default for FormatConfig {
+    fn default() -> Self {
+        FormatConfig {
+            summary: Template::parse("{?artist}{?title}{artist} - {title}{/title}{/artist}")
+                .unwrap(),
+            body: Template::parse("{album}{station}{status}\n{track}{?time}, {time}{/time}")
+                .unwrap(),
+            status: StatusFormat::default(),
+            playing: StatusTemplates::default(),
+            paused: StatusTemplates::default(),
+            stopped: StatusTemplates::default(),
+        }
+    }
+}
+
+impl FormatConfig {
+    fn for_status(&self, status: &PlaybackStatus) -> Option<&StatusTemplates> {
+        match status {
+            PlaybackStatus::Playing => Some(&self.playing),
+            PlaybackStatus::Paused => Some(&self.paused),
+            PlaybackStatus::Stopped => Some(&self.stopped),
+            PlaybackStatus::Unknown(_) => None,
+        }
+    }
+
+    /// Template of the summary for the given playback status.
+    pub fn summary(&self, status: &PlaybackStatus) -> &Template {
+        self.for_status(status)
+            .and_then(|t| t.summary.as_ref())
+            .unwrap_or(&self.summary)
+    }
+
+    /// Template of the body for the given playback status.
+    pub fn body(&self, status: &PlaybackStatus) -> &Template {
+        self.for_status(status)
+            .and_then(|t| t.body.as_ref())
+            .unwrap_or(&self.body)
+    }
+}
+
+impl Default for StatusFormat {
+    fn default() -> Self {
+        StatusFormat {
+            playing: String::new(),
+            paused: String::from(" [Paused]"),
+            stopped: String::from(" [Stopped]"),
+        }
+    }
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        DaemonConfig {
+            interval: 1000,
+            reconnect_interval: 5000,
+        }
+    }
+}
+
+impl Default for I3barConfig {
+    fn default() -> Self {
+        I3barConfig {
+            playing: String::from("#00FF00"),
+            paused: String::from("#FFFF00"),
+            stopped: String::from("#888888"),
+        }
+    }
+}
+
+impl Config {
+    /// Load the configuration from `$XDG_CONFIG_HOME/cmus-notify/config.toml`.
+    ///
+    /// A missing file is not an error, the defaults are used instead.
+    pub fn load() -> Result<Config, ConfigError> {
+        match get_config_path() {
+            Some(path) if path.exists() => Config::from_file(&path),
+            _ => Ok(Config::default()),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Config, ConfigError> {
+        let data = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_owned(), e))?;
+
+        Config::parse(&data).map_err(|e| match e {
+            ConfigError::Parse(_, e) => ConfigError::Parse(path.to_owned(), e),
+            ConfigError::Invalid(_, msg) => ConfigError::Invalid(path.to_owned(), msg),
+            e => e,
+        })
+    }
+
+    /// Override the connection settings with the environment, then with the `--server ADDRESS`,
+    /// `--socket PATH` and `--passwd PASSWORD` options at the start of `args`, which are removed.
+    ///
+    /// `var` returns the value of an environment variable.
+    pub fn apply_overrides<F>(&mut self, args: &mut Vec<String>, var: F)
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        if let Some(server) = var(SERVER_ENV).filter(|s| !s.is_empty()) {
+            self.connection.server = Some(server);
+        }
+
+        if let Some(socket) = var(SOCKET_ENV).filter(|s| !s.is_empty()) {
+            self.connection.socket = Some(PathBuf::from(socket));
+        }
+
+        if let Some(password) = var(PASSWORD_ENV) {
+            self.connection.password = Some(password);
+        }
+
+        while args.len() >= 2 {
+            match args[0].as_str() {
+                "--server" => self.connection.server = Some(args[1].clone()),
+                "--socket" => self.connection.socket = Some(PathBuf::from(&args[1])),
+                "--passwd" => self.connection.password = Some(args[1].clone()),
+                _ => break,
+            }
+
+            args.drain(..2);
+        }
+    }
+
+    fn parse(data: &str) -> Result<Config, ConfigError> {
+        let config: Config =
+            toml::from_str(data).map_err(|e| ConfigError::Parse(PathBuf::new(), e))?;
+
+        config
+            .validate()
+            .map_err(|msg| ConfigError::Invalid(PathBuf::new(), msg))?;
+
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.notification.icon.is_empty() {
+            return Err(String::from("notification.icon must not be empty"));
+        }
+
+        if self.notification.timeout == Some(0) {
+            return Err(String::from(
+                "notification.timeout must be greater than 0, remove it to use the server default",
+            ));
+        }
+
+        let notification = &self.notification;
+        if notification.backends.is_empty() {
+            return Err(String::from("notification.backends must not be empty"));
+        }
+
+        if notification.backends.contains(&Backend::Command) && notification.command.is_empty() {
+            return Err(String::from(
+                "notification.command must be set to use the command backend",
+            ));
+        }
+
+        if notification.backends.contains(&Backend::File) && notification.file.is_none() {
+            return Err(String::from(
+                "notification.file must be set to use the file backend",
+            ));
+        }
+
+        let connection = &self.connection;
+        if connection.connect_timeout == 0
+            || connection.read_timeout == 0
+            || connection.write_timeout == 0
+        {
+            return Err(String::from("connection timeouts must be greater than 0"));
+        }
+
+        if connection.server.as_deref() == Some("") {
+            return Err(String::from(
+                "connection.server must not be empty, remove it to use the default socket",
+            ));
+        }
+
+        if connection.socket.as_deref() == Some(Path::new("")) {
+            return Err(String::from(
+                "connection.socket must not be empty, remove it to use the default socket",
+            ));
+        }
+
+        if self.daemon.interval == 0 {
+            return Err(String::from("daemon.interval must be greater than 0"));
+        }
+
+        if self.daemon.reconnect_interval == 0 {
+            return Err(String::from(
+                "daemon.reconnect_interval must be greater than 0",
+            ));
+        }
+
+        for name in &self.cover.patterns {
+            if name.is_empty() {
+                return Err(String::from(
+                    "cover.patterns must not contain empty patterns",
+                ));
+            }
+
+            if name.contains('/') {
+                return Err(format!(
+                    "cover.patterns must contain file names, not paths: {:?}",
+                    name
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn get_config_path() -> Option<PathBuf> {
+    let mut path = dirs::config_dir()?;
+    path.push("cmus-notify");
+    path.push("config.toml");
+
+    Some(path)
+}
+
+#[cfg(test)]
+mod test_config {
+    use super::{Backend, Config, ConfigError, ErrorPolicy};
+    use crate::control::to_args;
+    use crate::event::Event;
+    use crate::template::Template;
+    use cmus_notify::PlaybackStatus;
+    use rstest::rstest;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_empty_config_is_default() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_partial_config() {
+        let config = Config::parse(
+            r##"
+[notification]
+icon = "audio-x-generic"
+timeout = 5000
+backends = ["dbus", "file"]
+file = "/tmp/notifications.log"
+
+[cover]
+patterns = ["folder.jpg", "AlbumArt*.jpg"]
+parent_levels = 1
+
+[format]
+summary = "{title|upper}"
+
+[format.status]
+paused = " (paused)"
+
+[format.paused]
+body = "{album} (paused)"
+
+[connection]
+server = "music-box:3000"
+password = "hunter2"
+
+[errors]
+policy = "log"
+
+[i3bar]
+paused = "#FFA500"
+"##,
+        )
+        .unwrap();
+
+        assert_eq!(config.notification.icon, "audio-x-generic");
+        assert_eq!(config.notification.timeout, Some(5000));
+        assert_eq!(config.notification.default_title, "C* Music Player");
+        assert!(config.notification.transient);
+        assert_eq!(config.notification.backends, [Backend::Dbus, Backend::File]);
+        assert_eq!(
+            config.notification.file,
+            Some(PathBuf::from("/tmp/notifications.log"))
+        );
+        assert_eq!(config.notification.command[0], "notify-send");
+        assert_eq!(
+            config.cover.patterns,
+            vec!["folder.jpg".to_string(), "AlbumArt*.jpg".to_string()]
+        );
+        assert_eq!(config.cover.parent_levels, 1);
+        assert!(!config.cover.any_image);
+        assert_eq!(config.format.status.paused, " (paused)");
+        assert_eq!(config.format.status.stopped, " [Stopped]");
+        assert_eq!(
+            config.format.summary,
+            Template::parse("{title|upper}").unwrap()
+        );
+        assert_eq!(
+            config.format.body(&PlaybackStatus::Playing),
+            &Config::default().format.body
+        );
+        assert_eq!(
+            config.format.body(&PlaybackStatus::Paused),
+            &Template::parse("{album} (paused)").unwrap()
+        );
+        assert_eq!(
+            config.format.summary(&PlaybackStatus::Paused),
+            &config.format.summary
+        );
+        assert_eq!(config.errors.policy, ErrorPolicy::Log);
+        assert_eq!(config.connection.server.as_deref(), Some("music-box:3000"));
+        assert_eq!(config.connection.password.as_deref(), Some("hunter2"));
+        assert_eq!(config.connection.read_timeout, 2000);
+        assert_eq!(config.i3bar.paused, "#FFA500");
+        assert_eq!(config.i3bar.playing, "#00FF00");
+    }
+
+    #[test]
+    fn test_events() {
+        let config = Config::parse("[events]\nnotify = [\"track-changed\", \"seeked\"]").unwrap();
+        assert_eq!(config.events.notify, [Event::TrackChanged, Event::Seeked]);
+
+        let config = Config::parse("[events]\nnotify = []").unwrap();
+        assert!(config.events.notify.is_empty());
+    }
+
+    #[test]
+    fn test_stream_icon() {
+        let config = Config::parse(
+            r#"
+[stream]
+icon = "radio"
+
+[stream.icons]
+"Radio Example" = "/icons/example.png"
+"http://radio.example/jazz" = "/icons/jazz.png"
+"#,
+        )
+        .unwrap();
+
+        let stream = &config.stream;
+        assert_eq!(
+            stream.icon("Radio Example", "http://radio.example/jazz"),
+            Some("/icons/example.png")
+        );
+        assert_eq!(
+            stream.icon("", "http://radio.example/jazz"),
+            Some("/icons/jazz.png")
+        );
+        assert_eq!(stream.icon("Other", "http://other/"), Some("radio"));
+        assert_eq!(
+            Config::default().stream.icon("Other", "http://other/"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_apply_overrides() {
+        let mut config = Config::default();
+        config.connection.server = Some(String::from("from-config"));
+        config.connection.password = Some(String::from("config-password"));
+
+        let mut args = to_args(&["--daemon"]);
+        config.apply_overrides(&mut args, |name| match name {
+            "CMUS_NOTIFY_SERVER" => Some(String::from("from-env:3000")),
+            _ => None,
+        });
+
+        assert_eq!(args, ["--daemon"]);
+        assert_eq!(config.connection.server.as_deref(), Some("from-env:3000"));
+        assert_eq!(
+            config.connection.password.as_deref(),
+            Some("config-password")
+        );
+
+        let mut args = to_args(&["--passwd", "flag", "--server", "/tmp/socket", "--mpris"]);
+        config.apply_overrides(&mut args, |name| match name {
+            "CMUS_NOTIFY_PASSWORD" => Some(String::from("env-password")),
+            "CMUS_NOTIFY_SOCKET" => Some(String::from("/run/cmus-socket")),
+            _ => None,
+        });
+
+        assert_eq!(args, ["--mpris"]);
+        assert_eq!(config.connection.server.as_deref(), Some("/tmp/socket"));
+        assert_eq!(config.connection.password.as_deref(), Some("flag"));
+        assert_eq!(
+            config.connection.socket,
+            Some(PathBuf::from("/run/cmus-socket"))
+        );
+
+        let mut args = to_args(&["--socket", "cmus.sock", "next"]);
+        config.apply_overrides(&mut args, |_| None);
+
+        assert_eq!(args, ["next"]);
+        assert_eq!(config.connection.socket, Some(PathBuf::from("cmus.sock")));
+    }
+
+    #[test]
+    fn test_apply_overrides_status_args() {
+        let mut config = Config::default();
+
+        // The value of a status field is never taken for an option.
+        let mut args = to_args(&["status", "playing", "title", "--server"]);
+        config.apply_overrides(&mut args, |_| None);
+
+        assert_eq!(args.len(), 4);
+        assert_eq!(config.connection.server, None);
+    }
+
+    #[rstest]
+    #[case::unknown_section("[foo]\nbar = 1")]
+    #[case::unknown_key("[notification]\nicn = \"foo\"")]
+    #[case::wrong_type("[notification]\ntimeout = \"5s\"")]
+    #[case::unknown_policy("[errors]\npolicy = \"ignore\"")]
+    #[case::unknown_backend("[notification]\nbackends = [\"email\"]")]
+    #[case::invalid_template("[format]\nbody = \"{albun}\"")]
+    #[case::unknown_event("[events]\nnotify = [\"volume-changed\"]")]
+    fn test_parse_error(#[case] data: &str) {
+        assert!(matches!(Config::parse(data), Err(ConfigError::Parse(_, _))));
+    }
+
+    #[rstest]
+    #[case::empty_icon("[notification]\nicon = \"\"")]
+    #[case::zero_timeout("[notification]\ntimeout = 0")]
+    #[case::no_backend("[notification]\nbackends = []")]
+    #[case::no_command("[notification]\nbackends = [\"command\"]\ncommand = []")]
+    #[case::no_file("[notification]\nbackends = [\"stdout\", \"file\"]")]
+    #[case::zero_timeout("[connection]\nread_timeout = 0")]
+    #[case::empty_server("[connection]\nserver = \"\"")]
+    #[case::empty_socket("[connection]\nsocket = \"\"")]
+    #[case::zero_interval("[daemon]\ninterval = 0")]
+    #[case::empty_cover_name("[cover]\npatterns = [\"\"]")]
+    #[case::cover_path("[cover]\npatterns = [\"art/cover.jpg\"]")]
+    fn test_invalid(#[case] data: &str) {
+        assert!(matches!(
+            Config::parse(data),
+            Err(ConfigError::Invalid(_, _))
+        ));
+    }
+}
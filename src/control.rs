@@ -0,0 +1,79 @@
+/// Map a control subcommand, like `next` or `vol -5%`, to its cmus command.
+///
+/// Returns `None` if the arguments are not a subcommand, and the usage when the subcommand is
+/// misused.
+pub fn get_command(args: &[String]) -> Option<Result<String, String>> {
+    let (name, rest) = args.split_first()?;
+
+    let (command, example) = match name.as_str() {
+        "play" => ("player-play", None),
+        "pause" => ("player-pause", None),
+        "next" => ("player-next", None),
+        "prev" => ("player-prev", None),
+        "stop" => ("player-stop", None),
+        "shuffle" => ("toggle shuffle", None),
+        "repeat" => ("toggle repeat", None),
+        "seek" => ("seek", Some("+10")),
+        "vol" => ("vol", Some("-5%")),
+        "raw" => ("", Some("\"<command>\"")),
+        _ => return None,
+    };
+
+    let command = match (example, rest) {
+        (None, []) => String::from(command),
+        (Some(_), [arg]) if !arg.trim().is_empty() => match command {
+            "" => arg.clone(),
+            command => format!("{} {}", command, arg),
+        },
+        (None, _) => return Some(Err(format!("usage: cmus-notify {}", name))),
+        (Some(example), _) => return Some(Err(format!("usage: cmus-notify {} {}", name, example))),
+    };
+
+    Some(Ok(command))
+}
+
+/// Command line arguments, for the tests of the modules parsing them.
+#[cfg(test)]
+pub(crate) fn to_args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|a| a.to_string()).collect()
+}
+
+#[cfg(test)]
+mod test_control {
+    use super::{get_command, to_args};
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(&["play"], "player-play")]
+    #[case(&["pause"], "player-pause")]
+    #[case(&["next"], "player-next")]
+    #[case(&["prev"], "player-prev")]
+    #[case(&["stop"], "player-stop")]
+    #[case(&["shuffle"], "toggle shuffle")]
+    #[case(&["repeat"], "toggle repeat")]
+    #[case(&["seek", "+10"], "seek +10")]
+    #[case(&["seek", "1:30"], "seek 1:30")]
+    #[case(&["vol", "-5%"], "vol -5%")]
+    #[case(&["raw", "set softvol=true"], "set softvol=true")]
+    fn test_get_command(#[case] args: &[&str], #[case] expected: &str) {
+        assert_eq!(get_command(&to_args(args)), Some(Ok(expected.to_string())));
+    }
+
+    #[rstest]
+    #[case(&["next", "now"])]
+    #[case(&["seek"])]
+    #[case(&["vol", "+5%", "-5%"])]
+    #[case(&["raw"])]
+    #[case(&["raw", " "])]
+    fn test_get_command_usage(#[case] args: &[&str]) {
+        assert!(matches!(get_command(&to_args(args)), Some(Err(_))));
+    }
+
+    #[rstest]
+    #[case(&[])]
+    #[case(&["status", "playing", "file", "/music/song.flac"])]
+    #[case(&["--daemon"])]
+    fn test_not_a_command(#[case] args: &[&str]) {
+        assert_eq!(get_command(&to_args(args)), None);
+    }
+}
@@ -0,0 +1,1205 @@
+//! Lookup of the cover of a track: image files next to it or picture embedded in it.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use serde::Deserialize;
+
+/// Picture type of the front cover, shared by FLAC and ID3v2.
+const FRONT_COVER: u32 = 3;
+
+/// Upper bound on the size of a picture, protects against corrupt length fields.
+const MAX_PICTURE_SIZE: u64 = 32 * 1024 * 1024;
+
+/// Cached covers unused for this long are deleted.
+const CACHE_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Extensions considered by the "any image" fallback.
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "gif", "webp", "bmp"];
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CoverConfig {
+    pub enabled: bool,
+    /// Case-insensitive glob patterns looked up in the directory of the playing file, in order.
+    pub patterns: Vec<String>,
+    /// Number of parent directories also searched with `patterns`.
+    pub parent_levels: u32,
+    /// Extract the cover embedded in the audio file if no cover file is found.
+    pub embedded: bool,
+    /// Use the first image of the directory as a last resort.
+    pub any_image: bool,
+}
+
+impl Default for CoverConfig {
+    fn default() -> Self {
+        CoverConfig {
+            enabled: true,
+            patterns: vec![String::from("cover.jpg"), String::from("cover.png")],
+            parent_levels: 0,
+            embedded: true,
+            any_image: false,
+        }
+    }
+}
+
+/// A picture embedded in an audio file.
+#[derive(Debug, PartialEq)]
+pub struct Picture {
+    pub mime: String,
+    pub kind: u32,
+    pub data: Vec<u8>,
+}
+
+impl Picture {
+    fn extension(&self) -> &'static str {
+        match self.mime.as_str() {
+            "image/png" => "png",
+            "image/gif" => "gif",
+            "image/webp" => "webp",
+            "image/bmp" => "bmp",
+            _ => "jpg",
+        }
+    }
+}
+
+/// Find the cover of the audio file `file`.
+///
+/// Cover files matching `config.patterns` come first, then the picture embedded in the file and
+/// finally any image of its directory, as enabled in `config`.
+pub fn find_cover(file: &Path, config: &CoverConfig) -> Option<PathBuf> {
+    if !config.enabled {
+        return None;
+    }
+
+    let directory = file.parent()?;
+
+    if let Some(cover) = find_cover_file(directory, config) {
+        return Some(cover);
+    }
+
+    if config.embedded {
+        if let Some(cover) = get_embedded_cover(file) {
+            return Some(cover);
+        }
+    }
+
+    if config.any_image {
+        return find_any_image(directory);
+    }
+
+    None
+}
+
+/// Look for a cover file matching `config.patterns` in `directory`, then in its parents.
+pub fn find_cover_file(directory: &Path, config: &CoverConfig) -> Option<PathBuf> {
+    for dir in directory
+        .ancestors()
+        .take(config.parent_levels as usize + 1)
+    {
+        let files = list_files(dir);
+
+        for pattern in &config.patterns {
+            if let Some(file) = files.iter().find(|f| glob_match(pattern, &file_name(f))) {
+                return Some(file.clone());
+            }
+        }
+    }
+
+    None
+}
+
+/// Return the first image of `directory`, in alphabetical order.
+pub fn find_any_image(directory: &Path) -> Option<PathBuf> {
+    list_files(directory).into_iter().find(|f| {
+        f.extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .is_some_and(|e| IMAGE_EXTENSIONS.contains(&e.as_str()))
+    })
+}
+
+/// Files of `directory`, sorted by name to get a stable order.
+fn list_files(directory: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = match fs::read_dir(directory) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.is_file())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    files.sort();
+
+    files
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Case-insensitive glob matching supporting `*`, `?` and `[...]` classes.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    // Position in the pattern after the last `*`, and in the name when it was seen.
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, n));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match match_class(&pattern[p..], name[n]) {
+                Some((true, len)) => Some(len),
+                Some((false, _)) => None,
+                // Unclosed class, match a literal `[`.
+                None if name[n] == '[' => Some(1),
+                None => None,
+            },
+            Some(&c) if c == name[n] => Some(1),
+            _ => None,
+        };
+
+        match (step, backtrack) {
+            (Some(len), _) => {
+                p += len;
+                n += 1;
+            }
+            (None, Some((bp, bn))) => {
+                p = bp;
+                n = bn + 1;
+                backtrack = Some((bp, bn + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Match `c` against the class at the start of `pattern`.
+///
+/// Returns whether it matched and the length of the class, or `None` if the class is not closed.
+fn match_class(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negate = matches!(pattern.get(i), Some('!') | Some('^'));
+    if negate {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+
+    loop {
+        match pattern.get(i) {
+            None => return None,
+            Some(']') if !first => break,
+            Some(&lo) => {
+                if pattern.get(i + 1) == Some(&'-')
+                    && pattern.get(i + 2).is_some_and(|&hi| hi != ']')
+                {
+                    matched |= lo <= c && c <= pattern[i + 2];
+                    i += 3;
+                } else {
+                    matched |= lo == c;
+                    i += 1;
+                }
+            }
+        }
+        first = false;
+    }
+
+    Some((matched != negate, i + 1))
+}
+
+/// Extract the cover embedded in `file` and write it to the cache directory.
+///
+/// Returns the path of the cached picture, or `None` if the file has no embedded cover.
+pub fn get_embedded_cover(file: &Path) -> Option<PathBuf> {
+    let mut dir = dirs::cache_dir()?;
+    dir.push("cmus-notify");
+    dir.push("covers");
+
+    cache_embedded_cover(file, &dir)
+}
+
+/// Each picture is cached once in `dir`, named after a hash of its data, so the tracks of an
+/// album share it. The `index` directory maps each audio file, by path, size and modification
+/// time, to the name of its picture.
+fn cache_embedded_cover(file: &Path, dir: &Path) -> Option<PathBuf> {
+    let metadata = fs::metadata(file).ok()?;
+
+    let mut hasher = DefaultHasher::new();
+    file.hash(&mut hasher);
+    metadata.len().hash(&mut hasher);
+    metadata.modified().ok().hash(&mut hasher);
+    let index = dir.join("index");
+    let entry = index.join(format!("{:016x}", hasher.finish()));
+
+    if let Ok(name) = fs::read_to_string(&entry) {
+        let path = dir.join(name.trim());
+        if path.is_file() {
+            // Keep the covers in use from being deleted as old.
+            touch(&entry);
+            touch(&path);
+            return Some(path);
+        }
+    }
+
+    let mut reader = BufReader::new(File::open(file).ok()?);
+    let picture = read_picture(&mut reader).ok()??;
+
+    let mut hasher = DefaultHasher::new();
+    picture.data.hash(&mut hasher);
+    let name = format!("{:016x}.{}", hasher.finish(), picture.extension());
+
+    fs::create_dir_all(&index).ok()?;
+    remove_old_files(dir, CACHE_MAX_AGE);
+    remove_old_files(&index, CACHE_MAX_AGE);
+
+    let path = dir.join(&name);
+    if path.is_file() {
+        touch(&path);
+    } else {
+        write_file(&path, &picture.data).ok()?;
+    }
+    // Without the entry the picture is read again next time, still found.
+    let _ = write_file(&entry, name.as_bytes());
+
+    Some(path)
+}
+
+/// Write to a temporary file first so a concurrent run never sees a partial file.
+fn write_file(path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path)
+}
+
+/// Mark a cached file as used.
+fn touch(path: &Path) {
+    let _ = File::options()
+        .write(true)
+        .open(path)
+        .and_then(|f| f.set_modified(SystemTime::now()));
+}
+
+/// Delete the files of `dir` not modified for `max_age`, sub-directories are kept.
+fn remove_old_files(dir: &Path, max_age: Duration) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let old = entry
+            .metadata()
+            .ok()
+            .filter(|m| m.is_file())
+            .and_then(|m| m.modified().ok())
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > max_age);
+
+        if old {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+/// Read the embedded pictures of an audio file and pick the front cover, or the first picture.
+pub fn read_picture<R: Read + Seek>(r: &mut R) -> io::Result<Option<Picture>> {
+    let mut magic = [0u8; 8];
+    r.read_exact(&mut magic)?;
+    r.seek(SeekFrom::Start(0))?;
+
+    let pictures = if &magic[..4] == b"fLaC" {
+        read_flac(r)?
+    } else if &magic[..3] == b"ID3" {
+        read_id3(r)?
+    } else if &magic[..4] == b"OggS" {
+        read_ogg(r)?
+    } else if &magic[4..] == b"ftyp" {
+        read_mp4(r)?
+    } else {
+        Vec::new()
+    };
+
+    let front = pictures
+        .iter()
+        .position(|p| p.kind == FRONT_COVER)
+        .unwrap_or(0);
+
+    Ok(pictures.into_iter().nth(front))
+}
+
+fn read_bytes<R: Read>(r: &mut R, len: u64) -> io::Result<Vec<u8>> {
+    if len > MAX_PICTURE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "block too large",
+        ));
+    }
+
+    let mut buf = vec![0u8; len as usize];
+    r.read_exact(&mut buf)?;
+
+    Ok(buf)
+}
+
+/// Cursor over a byte slice returning `None` when running out of data.
+struct Bytes<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Bytes<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Bytes { data, pos: 0 }
+    }
+
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(n)?;
+        let bytes = self.data.get(self.pos..end)?;
+        self.pos = end;
+
+        Some(bytes)
+    }
+
+    fn u32_be(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u32_le(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+}
+
+/// Parse a FLAC PICTURE block, also used by Ogg's METADATA_BLOCK_PICTURE.
+fn parse_picture_block(block: &[u8]) -> Option<Picture> {
+    let mut b = Bytes::new(block);
+
+    let kind = b.u32_be()?;
+    let mime_len = b.u32_be()? as usize;
+    let mime = String::from_utf8_lossy(b.take(mime_len)?).into_owned();
+    let desc_len = b.u32_be()? as usize;
+    b.take(desc_len)?;
+    // Width, height, color depth and number of colors.
+    b.take(16)?;
+    let data_len = b.u32_be()? as usize;
+    let data = b.take(data_len)?.to_vec();
+
+    Some(Picture { mime, kind, data })
+}
+
+fn read_flac<R: Read + Seek>(r: &mut R) -> io::Result<Vec<Picture>> {
+    let mut pictures = Vec::new();
+
+    r.seek(SeekFrom::Start(4))?;
+
+    loop {
+        let mut header = [0u8; 4];
+        r.read_exact(&mut header)?;
+
+        let last = header[0] & 0x80 != 0;
+        let kind = header[0] & 0x7f;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]);
+
+        if kind == 6 {
+            let block = read_bytes(r, len as u64)?;
+            pictures.extend(parse_picture_block(&block));
+        } else {
+            r.seek(SeekFrom::Current(len as i64))?;
+        }
+
+        if last {
+            break;
+        }
+    }
+
+    Ok(pictures)
+}
+
+fn syncsafe(b: &[u8]) -> u32 {
+    b.iter().fold(0, |acc, &x| (acc << 7) | (x & 0x7f) as u32)
+}
+
+fn read_id3<R: Read>(r: &mut R) -> io::Result<Vec<Picture>> {
+    let mut header = [0u8; 10];
+    r.read_exact(&mut header)?;
+
+    let major = header[3];
+    let flags = header[5];
+    let mut tag = read_bytes(r, syncsafe(&header[6..10]) as u64)?;
+
+    if flags & 0x80 != 0 && major < 4 {
+        // Unsynchronisation: every 0xFF 0x00 pair was written for a 0xFF.
+        let mut prev = 0;
+        tag.retain(|&x| {
+            let keep = !(prev == 0xff && x == 0x00);
+            prev = x;
+            keep
+        });
+    }
+
+    let mut b = Bytes::new(&tag);
+
+    if flags & 0x40 != 0 {
+        let ext = b.take(4).unwrap_or_default();
+        let skip = match major {
+            4 => syncsafe(ext).saturating_sub(4),
+            _ => u32::from_be_bytes(ext.try_into().unwrap_or_default()),
+        };
+        b.take(skip as usize);
+    }
+
+    let mut pictures = Vec::new();
+
+    loop {
+        let (id, size) = match major {
+            2 => match b.take(6) {
+                Some(h) => (&h[..3], u32::from_be_bytes([0, h[3], h[4], h[5]])),
+                None => break,
+            },
+            _ => match b.take(10) {
+                Some(h) if major == 4 => (&h[..4], syncsafe(&h[4..8])),
+                Some(h) => (&h[..4], u32::from_be_bytes([h[4], h[5], h[6], h[7]])),
+                None => break,
+            },
+        };
+
+        // Padding.
+        if id[0] == 0 {
+            break;
+        }
+
+        let frame = match b.take(size as usize) {
+            Some(f) => f,
+            None => break,
+        };
+
+        if id == b"APIC" || id == b"PIC" {
+            pictures.extend(parse_apic(frame, major == 2));
+        }
+    }
+
+    Ok(pictures)
+}
+
+fn parse_apic(frame: &[u8], v22: bool) -> Option<Picture> {
+    let encoding = *frame.first()?;
+    let mut pos = 1;
+
+    let mime = if v22 {
+        let format = frame.get(1..4)?;
+        pos = 4;
+        String::from_utf8_lossy(format).to_lowercase()
+    } else {
+        let len = frame[pos..].iter().position(|&x| x == 0)?;
+        let mime = String::from_utf8_lossy(&frame[pos..pos + len]).to_lowercase();
+        pos += len + 1;
+        mime
+    };
+
+    let mime = match mime.as_str() {
+        m if m.contains('/') => mime,
+        "png" => String::from("image/png"),
+        _ => String::from("image/jpeg"),
+    };
+
+    let kind = *frame.get(pos)? as u32;
+    pos += 1;
+
+    // Skip the description, null terminated in the frame's encoding.
+    if encoding == 1 || encoding == 2 {
+        let len = frame[pos..].chunks(2).position(|c| c == [0, 0])?;
+        pos += len * 2 + 2;
+    } else {
+        let len = frame[pos..].iter().position(|&x| x == 0)?;
+        pos += len + 1;
+    }
+
+    Some(Picture {
+        mime,
+        kind,
+        data: frame.get(pos..)?.to_vec(),
+    })
+}
+
+/// Find the atom `name` between `start` and `end`, returning the bounds of its content.
+fn find_atom<R: Read + Seek>(
+    r: &mut R,
+    start: u64,
+    end: u64,
+    name: &[u8],
+) -> io::Result<Option<(u64, u64)>> {
+    let mut pos = start;
+
+    while pos + 8 <= end {
+        r.seek(SeekFrom::Start(pos))?;
+
+        let mut header = [0u8; 8];
+        r.read_exact(&mut header)?;
+
+        let mut size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let mut header_len = 8;
+
+        if size == 1 {
+            let mut large = [0u8; 8];
+            r.read_exact(&mut large)?;
+            size = u64::from_be_bytes(large);
+            header_len = 16;
+        } else if size == 0 {
+            size = end - pos;
+        }
+
+        if size < header_len {
+            break;
+        }
+
+        // A corrupt 64-bit size must not wrap around to the start of the file.
+        let next = match pos.checked_add(size) {
+            Some(next) => next,
+            None => break,
+        };
+
+        if &header[4..] == name {
+            return Ok(Some((pos + header_len, next.min(end))));
+        }
+
+        if next > end {
+            break;
+        }
+
+        pos = next;
+    }
+
+    Ok(None)
+}
+
+fn read_mp4<R: Read + Seek>(r: &mut R) -> io::Result<Vec<Picture>> {
+    let end = r.seek(SeekFrom::End(0))?;
+    let mut bounds = (0, end);
+
+    for name in [&b"moov"[..], b"udta", b"meta", b"ilst", b"covr"] {
+        bounds = match find_atom(r, bounds.0, bounds.1, name)? {
+            // `meta` is a full atom, its content starts after the version and flags.
+            Some((start, end)) if name == b"meta" => (start + 4, end),
+            Some(b) => b,
+            None => return Ok(Vec::new()),
+        };
+    }
+
+    let mut pictures = Vec::new();
+    let (mut start, end) = bounds;
+
+    while let Some((data_start, data_end)) = find_atom(r, start, end, b"data")? {
+        // Type indicator and locale.
+        if data_end < data_start + 8 {
+            break;
+        }
+
+        r.seek(SeekFrom::Start(data_start))?;
+        let mut kind = [0u8; 8];
+        r.read_exact(&mut kind)?;
+
+        let mime = match u32::from_be_bytes([kind[0], kind[1], kind[2], kind[3]]) {
+            14 => "image/png",
+            27 => "image/bmp",
+            _ => "image/jpeg",
+        };
+
+        pictures.push(Picture {
+            mime: String::from(mime),
+            // MP4 has no picture types, the first one is the cover.
+            kind: if pictures.is_empty() { FRONT_COVER } else { 0 },
+            data: read_bytes(r, data_end - data_start - 8)?,
+        });
+
+        start = data_end;
+    }
+
+    Ok(pictures)
+}
+
+/// Read the first `count` packets of the first logical stream of an Ogg file.
+fn read_ogg_packets<R: Read>(r: &mut R, count: usize) -> io::Result<Vec<Vec<u8>>> {
+    let mut packets = Vec::new();
+    let mut packet = Vec::new();
+    let mut serial = None;
+
+    while packets.len() < count {
+        let mut header = [0u8; 27];
+        r.read_exact(&mut header)?;
+
+        if &header[..4] != b"OggS" {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "bad ogg page"));
+        }
+
+        let mut segments = vec![0u8; header[26] as usize];
+        r.read_exact(&mut segments)?;
+
+        let payload = read_bytes(r, segments.iter().map(|&s| s as u64).sum())?;
+
+        let page_serial = u32::from_le_bytes([header[14], header[15], header[16], header[17]]);
+        if *serial.get_or_insert(page_serial) != page_serial {
+            continue;
+        }
+
+        let mut pos = 0;
+        for &len in &segments {
+            packet.extend_from_slice(&payload[pos..pos + len as usize]);
+            pos += len as usize;
+
+            if len < 255 {
+                packets.push(std::mem::take(&mut packet));
+            }
+
+            if packet.len() as u64 > MAX_PICTURE_SIZE * 2 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "packet too large",
+                ));
+            }
+        }
+    }
+
+    Ok(packets)
+}
+
+fn read_ogg<R: Read>(r: &mut R) -> io::Result<Vec<Picture>> {
+    let packets = read_ogg_packets(r, 2)?;
+
+    let comments = match packets.get(1) {
+        Some(p) if p.starts_with(b"\x03vorbis") => &p[7..],
+        Some(p) if p.starts_with(b"OpusTags") => &p[8..],
+        _ => return Ok(Vec::new()),
+    };
+
+    let mut b = Bytes::new(comments);
+    let mut pictures = Vec::new();
+
+    let vendor_len = b.u32_le().unwrap_or_default() as usize;
+    b.take(vendor_len);
+
+    for _ in 0..b.u32_le().unwrap_or_default() {
+        let comment = match b.u32_le().and_then(|len| b.take(len as usize)) {
+            Some(c) => c,
+            None => break,
+        };
+
+        let key = b"METADATA_BLOCK_PICTURE=";
+        if comment.len() > key.len() && comment[..key.len()].eq_ignore_ascii_case(key) {
+            pictures
+                .extend(decode_base64(&comment[key.len()..]).and_then(|b| parse_picture_block(&b)));
+        }
+    }
+
+    Ok(pictures)
+}
+
+fn decode_base64(data: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len() * 3 / 4);
+    let mut acc: u32 = 0;
+    let mut bits = 0;
+
+    for &c in data {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' | b'\r' | b'\n' => continue,
+            _ => return None,
+        };
+
+        acc = (acc << 6) | v as u32;
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod test_cover_file {
+    use super::{find_any_image, find_cover, find_cover_file, glob_match, CoverConfig};
+    use rstest::rstest;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    #[rstest]
+    #[case("cover.jpg", "cover.jpg", true)]
+    #[case("cover.jpg", "Cover.JPG", true)]
+    #[case("cover.jpg", "cover.png", false)]
+    #[case("*.webp", "front.webp", true)]
+    #[case("*.webp", "front.webp.bak", false)]
+    #[case("AlbumArt*.jpg", "AlbumArt_{ABC}_Large.jpg", true)]
+    #[case("AlbumArt*.jpg", "albumartsmall.jpg", true)]
+    #[case("AlbumArt*.jpg", "folder.jpg", false)]
+    #[case("front.???", "front.png", true)]
+    #[case("front.???", "front.jpeg", false)]
+    #[case("*cover*", "my-cover-art.png", true)]
+    #[case("cover.[jp][pn]g", "cover.png", true)]
+    #[case("cover.[!j]*", "cover.jpg", false)]
+    #[case("cd[0-9]", "CD2", true)]
+    #[case("[abc", "[abc", true)]
+    fn test_glob_match(#[case] pattern: &str, #[case] name: &str, #[case] expected: bool) {
+        assert_eq!(glob_match(pattern, name), expected);
+    }
+
+    fn make_tree(name: &str, files: &[&str]) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("cmus-notify-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        for f in files {
+            let path = root.join(f);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"").unwrap();
+        }
+
+        root
+    }
+
+    fn config(patterns: &[&str], parent_levels: u32) -> CoverConfig {
+        CoverConfig {
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            parent_levels,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_find_cover_file_pattern_order() {
+        let root = make_tree("order", &["Folder.jpg", "front.png", "01.flac"]);
+
+        let found = find_cover_file(&root, &config(&["cover.*", "front.*", "folder.*"], 0));
+        assert_eq!(found, Some(root.join("front.png")));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_find_cover_file_parent() {
+        let root = make_tree("parent", &["Cover.JPG", "CD1/01.flac"]);
+        let disc = root.join("CD1");
+
+        assert_eq!(find_cover_file(&disc, &config(&["cover.jpg"], 0)), None);
+        assert_eq!(
+            find_cover_file(&disc, &config(&["cover.jpg"], 1)),
+            Some(root.join("Cover.JPG"))
+        );
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_find_any_image() {
+        let root = make_tree(
+            "any",
+            &["01.flac", "scan-b.PNG", "scan-a.txt", "booklet/a.jpg"],
+        );
+
+        assert_eq!(find_any_image(&root), Some(root.join("scan-b.PNG")));
+        assert_eq!(find_any_image(Path::new("/nonexistent")), None);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_find_cover() {
+        let root = make_tree("find", &["01.flac", "folder.jpg", "scan.png"]);
+        let file = root.join("01.flac");
+
+        let mut config = config(&["folder.*"], 0);
+        assert_eq!(find_cover(&file, &config), Some(root.join("folder.jpg")));
+
+        config.patterns = vec![String::from("cover.*")];
+        assert_eq!(find_cover(&file, &config), None);
+
+        config.any_image = true;
+        assert_eq!(find_cover(&file, &config), Some(root.join("folder.jpg")));
+
+        config.enabled = false;
+        assert_eq!(find_cover(&file, &config), None);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test_cover {
+    use super::{
+        cache_embedded_cover, decode_base64, read_picture, remove_old_files, Picture, FRONT_COVER,
+    };
+    use std::fs;
+    use std::io::Cursor;
+    use std::time::{Duration, SystemTime};
+
+    fn picture_block(kind: u32, mime: &str, data: &[u8]) -> Vec<u8> {
+        let mut block = Vec::new();
+        block.extend(kind.to_be_bytes());
+        block.extend((mime.len() as u32).to_be_bytes());
+        block.extend(mime.as_bytes());
+        block.extend(4u32.to_be_bytes());
+        block.extend(b"desc");
+        block.extend([0u8; 16]);
+        block.extend((data.len() as u32).to_be_bytes());
+        block.extend(data);
+        block
+    }
+
+    fn encode_base64(data: &[u8]) -> Vec<u8> {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut out = Vec::new();
+
+        for chunk in data.chunks(3) {
+            let n =
+                chunk.iter().fold(0u32, |acc, &b| (acc << 8) | b as u32) << (8 * (3 - chunk.len()));
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    out.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 0x3f]);
+                } else {
+                    out.push(b'=');
+                }
+            }
+        }
+
+        out
+    }
+
+    fn read(data: Vec<u8>) -> Option<Picture> {
+        read_picture(&mut Cursor::new(data)).unwrap()
+    }
+
+    #[test]
+    fn test_flac() {
+        let mut file = b"fLaC".to_vec();
+        // STREAMINFO
+        file.extend([0x00, 0, 0, 4, 1, 2, 3, 4]);
+        for (i, (kind, data)) in [(0u32, &b"other"[..]), (FRONT_COVER, b"front")]
+            .iter()
+            .enumerate()
+        {
+            let block = picture_block(*kind, "image/png", data);
+            let flag = if i == 1 { 0x80 } else { 0 };
+            file.push(flag | 6);
+            file.extend(&(block.len() as u32).to_be_bytes()[1..]);
+            file.extend(block);
+        }
+        file.extend(b"audio frames");
+
+        assert_eq!(
+            read(file),
+            Some(Picture {
+                mime: "image/png".to_string(),
+                kind: FRONT_COVER,
+                data: b"front".to_vec(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_flac_without_picture() {
+        let mut file = b"fLaC".to_vec();
+        file.extend([0x80, 0, 0, 4, 1, 2, 3, 4]);
+
+        assert_eq!(read(file), None);
+    }
+
+    fn id3_tag(major: u8, frames: &[u8]) -> Vec<u8> {
+        let size = frames.len() as u32 + 16;
+        let mut tag = b"ID3".to_vec();
+        tag.extend([major, 0, 0]);
+        tag.extend([
+            (size >> 21) as u8 & 0x7f,
+            (size >> 14) as u8 & 0x7f,
+            (size >> 7) as u8 & 0x7f,
+            size as u8 & 0x7f,
+        ]);
+        tag.extend(frames);
+        // Padding
+        tag.extend([0u8; 16]);
+        tag.extend(b"mpeg frames");
+        tag
+    }
+
+    #[test]
+    fn test_id3v23() {
+        let mut frames = Vec::new();
+
+        let mut tit2 = vec![0];
+        tit2.extend(b"Title");
+        frames.extend(b"TIT2");
+        frames.extend((tit2.len() as u32).to_be_bytes());
+        frames.extend([0, 0]);
+        frames.extend(tit2);
+
+        let mut apic = vec![1];
+        apic.extend(b"image/jpeg\0");
+        apic.push(FRONT_COVER as u8);
+        apic.extend([0xff, 0xfe, b'c', 0, 0, 0]);
+        apic.extend(b"jpeg data");
+        frames.extend(b"APIC");
+        frames.extend((apic.len() as u32).to_be_bytes());
+        frames.extend([0, 0]);
+        frames.extend(apic);
+
+        assert_eq!(
+            read(id3_tag(3, &frames)),
+            Some(Picture {
+                mime: "image/jpeg".to_string(),
+                kind: FRONT_COVER,
+                data: b"jpeg data".to_vec(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_id3v22() {
+        let mut pic = vec![0];
+        pic.extend(b"PNG");
+        pic.push(0);
+        pic.extend(b"desc\0");
+        pic.extend(b"png data");
+
+        let mut frames = b"PIC".to_vec();
+        frames.extend(&(pic.len() as u32).to_be_bytes()[1..]);
+        frames.extend(pic);
+
+        assert_eq!(
+            read(id3_tag(2, &frames)),
+            Some(Picture {
+                mime: "image/png".to_string(),
+                kind: 0,
+                data: b"png data".to_vec(),
+            })
+        );
+    }
+
+    fn atom(name: &[u8], content: &[u8]) -> Vec<u8> {
+        let mut atom = ((content.len() + 8) as u32).to_be_bytes().to_vec();
+        atom.extend(name);
+        atom.extend(content);
+        atom
+    }
+
+    #[test]
+    fn test_mp4() {
+        let mut data = 14u32.to_be_bytes().to_vec();
+        data.extend([0u8; 4]);
+        data.extend(b"png data");
+
+        let covr = atom(b"covr", &atom(b"data", &data));
+        let ilst = atom(b"ilst", &covr);
+        let mut meta = vec![0u8; 4];
+        meta.extend(atom(b"hdlr", &[0u8; 8]));
+        meta.extend(ilst);
+        let udta = atom(b"udta", &atom(b"meta", &meta));
+        let mut moov = atom(b"mvhd", &[0u8; 12]);
+        moov.extend(udta);
+
+        let mut file = atom(b"ftyp", b"M4A ");
+        file.extend(atom(b"moov", &moov));
+        file.extend(atom(b"mdat", b"audio"));
+
+        assert_eq!(
+            read(file),
+            Some(Picture {
+                mime: "image/png".to_string(),
+                kind: FRONT_COVER,
+                data: b"png data".to_vec(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_extension() {
+        let picture = |mime: &str| Picture {
+            mime: mime.to_string(),
+            kind: 0,
+            data: Vec::new(),
+        };
+
+        assert_eq!(picture("image/png").extension(), "png");
+        assert_eq!(picture("image/bmp").extension(), "bmp");
+        assert_eq!(picture("image/jpeg").extension(), "jpg");
+        assert_eq!(picture("").extension(), "jpg");
+    }
+
+    #[test]
+    fn test_mp4_large_size_overflow() {
+        let mut file = atom(b"ftyp", b"M4A ");
+        file.extend(1u32.to_be_bytes());
+        file.extend(b"free");
+        file.extend((u64::MAX - 8).to_be_bytes());
+        file.extend(atom(b"moov", &[0u8; 4]));
+
+        assert_eq!(read(file), None);
+    }
+
+    fn ogg_page(seq: u32, packets: &[&[u8]]) -> Vec<u8> {
+        let mut segments = Vec::new();
+        let mut payload: Vec<u8> = Vec::new();
+        for p in packets {
+            let mut len = p.len();
+            while len >= 255 {
+                segments.push(255);
+                len -= 255;
+            }
+            segments.push(len as u8);
+            payload.extend(*p);
+        }
+
+        let mut page = b"OggS".to_vec();
+        page.extend([0, 0]);
+        page.extend([0u8; 8]);
+        page.extend(1u32.to_le_bytes());
+        page.extend(seq.to_le_bytes());
+        page.extend([0u8; 4]);
+        page.push(segments.len() as u8);
+        page.extend(segments);
+        page.extend(payload);
+        page
+    }
+
+    #[test]
+    fn test_ogg_vorbis() {
+        let block = picture_block(FRONT_COVER, "image/jpeg", &[0x42; 300]);
+
+        let mut comment = b"METADATA_BLOCK_PICTURE=".to_vec();
+        comment.extend(encode_base64(&block));
+
+        let mut tags = b"\x03vorbis".to_vec();
+        tags.extend(6u32.to_le_bytes());
+        tags.extend(b"vendor");
+        tags.extend(2u32.to_le_bytes());
+        tags.extend(12u32.to_le_bytes());
+        tags.extend(b"TITLE=Title!");
+        tags.extend((comment.len() as u32).to_le_bytes());
+        tags.extend(comment);
+
+        let mut file = ogg_page(0, &[b"\x01vorbis ident"]);
+        file.extend(ogg_page(1, &[&tags, b"\x05vorbis setup"]));
+
+        assert_eq!(
+            read(file),
+            Some(Picture {
+                mime: "image/jpeg".to_string(),
+                kind: FRONT_COVER,
+                data: vec![0x42; 300],
+            })
+        );
+    }
+
+    #[test]
+    fn test_unknown_format() {
+        assert_eq!(read(b"RIFF\0\0\0\0WAVEfmt ".to_vec()), None);
+    }
+
+    #[test]
+    fn test_decode_base64() {
+        assert_eq!(decode_base64(b"TWFu"), Some(b"Man".to_vec()));
+        assert_eq!(decode_base64(b"TWE="), Some(b"Ma".to_vec()));
+        assert_eq!(decode_base64(b"TQ=="), Some(b"M".to_vec()));
+        assert_eq!(decode_base64(b"T!=="), None);
+        assert_eq!(
+            decode_base64(&encode_base64(b"hello world")),
+            Some(b"hello world".to_vec())
+        );
+    }
+
+    fn flac_with_cover(data: &[u8], audio: &[u8]) -> Vec<u8> {
+        let block = picture_block(FRONT_COVER, "image/png", data);
+        let mut file = b"fLaC".to_vec();
+        file.push(0x80 | 6);
+        file.extend(&(block.len() as u32).to_be_bytes()[1..]);
+        file.extend(block);
+        file.extend(audio);
+        file
+    }
+
+    #[test]
+    fn test_cache_embedded_cover() {
+        let dir =
+            std::env::temp_dir().join(format!("cmus-notify-cover-cache-{}", std::process::id()));
+        let cache = dir.join("covers");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        for (name, data, audio) in [
+            ("01.flac", &b"front"[..], &b"first"[..]),
+            ("02.flac", b"front", b"second"),
+            ("03.flac", b"other", b"third"),
+        ] {
+            fs::write(dir.join(name), flac_with_cover(data, audio)).unwrap();
+        }
+
+        let first = cache_embedded_cover(&dir.join("01.flac"), &cache).unwrap();
+        assert_eq!(fs::read(&first).unwrap(), b"front");
+        assert_eq!(first.extension().unwrap(), "png");
+        // Same picture, same cache file.
+        assert_eq!(
+            cache_embedded_cover(&dir.join("02.flac"), &cache),
+            Some(first.clone())
+        );
+        // Found through the index.
+        assert_eq!(
+            cache_embedded_cover(&dir.join("01.flac"), &cache),
+            Some(first.clone())
+        );
+
+        let other = cache_embedded_cover(&dir.join("03.flac"), &cache).unwrap();
+        assert_ne!(other, first);
+
+        let count = |dir| fs::read_dir(dir).unwrap().count();
+        // Two pictures and the index.
+        assert_eq!(count(&cache), 3);
+        assert_eq!(count(&cache.join("index")), 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_remove_old_files() {
+        let dir =
+            std::env::temp_dir().join(format!("cmus-notify-cover-old-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("index")).unwrap();
+        fs::write(dir.join("old.jpg"), b"old").unwrap();
+        fs::write(dir.join("new.jpg"), b"new").unwrap();
+
+        let old = SystemTime::now() - Duration::from_secs(3600);
+        fs::File::options()
+            .write(true)
+            .open(dir.join("old.jpg"))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+
+        remove_old_files(&dir, Duration::from_secs(60));
+
+        assert!(!dir.join("old.jpg").exists());
+        assert!(dir.join("new.jpg").exists());
+        assert!(dir.join("index").is_dir());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
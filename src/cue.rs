@@ -0,0 +1,241 @@
+//! Tracks of CUE sheets, which cmus plays as `cue:///path/album.cue/3`.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Frames per second of the `mm:ss:ff` times of a CUE sheet.
+const FRAMES_PER_SECOND: u64 = 75;
+
+/// The album described by a CUE sheet.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CueSheet {
+    pub title: String,
+    pub performer: String,
+    /// From `REM DATE`.
+    pub date: String,
+    pub tracks: Vec<Track>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Track {
+    pub number: u32,
+    pub title: String,
+    pub performer: String,
+    /// Audio file containing the track, relative to the directory of the CUE sheet if it is not
+    /// absolute.
+    pub file: PathBuf,
+    /// Start of the track in `file`, from `INDEX 01`.
+    pub start: Option<Duration>,
+}
+
+/// The CUE sheet and the track number of a `cue://` path, `None` for other paths.
+pub fn parse_path(file: &str) -> Option<(PathBuf, u32)> {
+    let (sheet, number) = file.strip_prefix("cue://")?.rsplit_once('/')?;
+
+    Some((PathBuf::from(sheet), number.parse().ok()?))
+}
+
+impl CueSheet {
+    /// Read the CUE sheet `path`. Invalid UTF-8 is replaced, like sheets written by old rippers.
+    pub fn read(path: &Path) -> io::Result<CueSheet> {
+        let data = fs::read(path)?;
+        let directory = path.parent().unwrap_or(Path::new(""));
+
+        Ok(CueSheet::parse(&String::from_utf8_lossy(&data), directory))
+    }
+
+    /// Parse a CUE sheet, with the files relative to `directory`. Unknown commands are ignored.
+    pub fn parse(data: &str, directory: &Path) -> CueSheet {
+        let mut sheet = CueSheet::default();
+        let mut file = PathBuf::new();
+
+        for line in data.trim_start_matches('\u{feff}').lines() {
+            let line = line.trim();
+            let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+
+            match command.to_ascii_uppercase().as_str() {
+                "FILE" => file = directory.join(parse_file_name(rest)),
+                "TRACK" => sheet.tracks.push(Track {
+                    number: rest
+                        .split_whitespace()
+                        .next()
+                        .and_then(|n| n.parse().ok())
+                        .unwrap_or_default(),
+                    file: file.clone(),
+                    ..Default::default()
+                }),
+                "TITLE" => *sheet.field(|t| &mut t.title, |s| &mut s.title) = unquote(rest),
+                "PERFORMER" => {
+                    *sheet.field(|t| &mut t.performer, |s| &mut s.performer) = unquote(rest)
+                }
+                "INDEX" => {
+                    let mut parts = rest.split_whitespace();
+                    if let (Some("01"), Some(time), Some(track)) =
+                        (parts.next(), parts.next(), sheet.tracks.last_mut())
+                    {
+                        track.start = parse_time(time);
+                    }
+                }
+                "REM" => {
+                    if let Some(("DATE", date)) = rest.split_once(char::is_whitespace) {
+                        sheet.date = unquote(date.trim());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        sheet
+    }
+
+    /// The field of the current track, or of the album before the first track.
+    fn field(
+        &mut self,
+        track: fn(&mut Track) -> &mut String,
+        album: fn(&mut CueSheet) -> &mut String,
+    ) -> &mut String {
+        if self.tracks.is_empty() {
+            album(self)
+        } else {
+            track(self.tracks.last_mut().unwrap())
+        }
+    }
+
+    pub fn track(&self, number: u32) -> Option<&Track> {
+        self.tracks.iter().find(|t| t.number == number)
+    }
+
+    /// Length of a track, up to the start of the next one in the same file. Unknown for the last
+    /// track of a file, which ends with the file.
+    pub fn duration(&self, number: u32) -> Option<Duration> {
+        let index = self.tracks.iter().position(|t| t.number == number)?;
+        let track = &self.tracks[index];
+        let next = self
+            .tracks
+            .get(index + 1)
+            .filter(|n| n.file == track.file)?;
+
+        next.start?.checked_sub(track.start?)
+    }
+}
+
+/// `"name.flac" WAVE` or `name.flac WAVE`: the file type comes last.
+fn parse_file_name(value: &str) -> String {
+    if value.starts_with('"') {
+        return unquote(value);
+    }
+
+    match value.rsplit_once(char::is_whitespace) {
+        Some((name, _)) => String::from(name.trim()),
+        None => String::from(value),
+    }
+}
+
+/// The text between the first and the last quote, or the whole value if it is not quoted.
+fn unquote(value: &str) -> String {
+    match value.strip_prefix('"').and_then(|v| v.rsplit_once('"')) {
+        Some((text, _)) => String::from(text),
+        None => String::from(value),
+    }
+}
+
+/// `mm:ss:ff`, with 75 frames per second.
+fn parse_time(time: &str) -> Option<Duration> {
+    let mut parts = time.split(':').map(|p| p.parse::<u64>().ok());
+
+    match (parts.next()?, parts.next()?, parts.next()?, parts.next()) {
+        (Some(minutes), Some(seconds), Some(frames), None) => Some(Duration::from_millis(
+            (minutes * 60 + seconds) * 1000 + frames * 1000 / FRAMES_PER_SECOND,
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test_cue {
+    use super::{parse_path, parse_time, CueSheet, Track};
+    use rstest::rstest;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    const SHEET: &str = "\u{feff}REM GENRE Metal
+REM DATE 1986
+PERFORMER \"Metallideth\"
+TITLE \"Orgasmatron\"
+FILE \"Metallideth - Orgasmatron.flac\" WAVE
+  TRACK 01 AUDIO
+    TITLE \"Intro\"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE \"Orgasmatron\"
+    PERFORMER \"Metallideth & Friends\"
+    INDEX 00 02:10:00
+    INDEX 01 02:12:37
+FILE bonus.flac WAVE
+  TRACK 03 AUDIO
+    TITLE \"Bonus \"Live\"\"
+    INDEX 01 00:00:00
+";
+
+    #[test]
+    fn test_parse() {
+        let sheet = CueSheet::parse(SHEET, Path::new("/music/album"));
+
+        assert_eq!(sheet.title, "Orgasmatron");
+        assert_eq!(sheet.performer, "Metallideth");
+        assert_eq!(sheet.date, "1986");
+        assert_eq!(
+            sheet.track(2),
+            Some(&Track {
+                number: 2,
+                title: String::from("Orgasmatron"),
+                performer: String::from("Metallideth & Friends"),
+                file: PathBuf::from("/music/album/Metallideth - Orgasmatron.flac"),
+                start: Some(Duration::from_millis(132_493)),
+            })
+        );
+        assert_eq!(sheet.track(1).unwrap().performer, "");
+        assert_eq!(sheet.track(3).unwrap().title, "Bonus \"Live\"");
+        assert_eq!(
+            sheet.track(3).unwrap().file,
+            PathBuf::from("/music/album/bonus.flac")
+        );
+        assert_eq!(sheet.track(4), None);
+    }
+
+    #[test]
+    fn test_duration() {
+        let sheet = CueSheet::parse(SHEET, Path::new("/music/album"));
+
+        assert_eq!(sheet.duration(1), Some(Duration::from_millis(132_493)));
+        // Last track of its file.
+        assert_eq!(sheet.duration(2), None);
+        assert_eq!(sheet.duration(3), None);
+    }
+
+    #[rstest]
+    #[case("cue:///music/album.cue/3", Some(("/music/album.cue", 3)))]
+    #[case("cue:///music/a/b.cue/12", Some(("/music/a/b.cue", 12)))]
+    #[case("cue:///music/album.cue", None)]
+    #[case("/music/album.flac", None)]
+    #[case("http://radio.example/3", None)]
+    fn test_parse_path(#[case] file: &str, #[case] expected: Option<(&str, u32)>) {
+        assert_eq!(
+            parse_path(file),
+            expected.map(|(path, n)| (PathBuf::from(path), n))
+        );
+    }
+
+    #[rstest]
+    #[case("00:00:00", Some(Duration::ZERO))]
+    #[case("03:25:74", Some(Duration::from_millis(205_986)))]
+    #[case("100:00:00", Some(Duration::from_secs(6000)))]
+    #[case("03:25", None)]
+    #[case("03:25:xx", None)]
+    fn test_parse_time(#[case] time: &str, #[case] expected: Option<Duration>) {
+        assert_eq!(parse_time(time), expected);
+    }
+}
@@ -0,0 +1,130 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use cmus_notify::cue::CueSheet;
+use cmus_notify::{CmusClient, Error, Metadata};
+
+use crate::actions;
+use crate::config::Config;
+use crate::event;
+use crate::logging;
+use crate::notification::{check_errors, notify_metadata};
+
+/// Query the status of cmus every `daemon.interval` over a persistent connection.
+///
+/// `on_status` is called with each status, the errors of its invalid fields and the cover of the
+/// file, or with the error when cmus cannot be reached. Tracks of CUE sheets are completed from
+/// the sheet when cmus is local. Reconnects when cmus is restarted, never returns.
+pub fn follow<F>(config: &Config, mut on_status: F)
+where
+    F: FnMut(Result<(Metadata, Vec<Error>, Option<PathBuf>), Error>),
+{
+    let interval = Duration::from_millis(config.daemon.interval);
+    let reconnect_interval = Duration::from_millis(config.daemon.reconnect_interval);
+    let local = config.connection.is_local();
+    // Reading the CUE sheet and looking for the cover on every poll is too expensive, they only
+    // change with the file.
+    let mut file = String::new();
+    let mut sheet: Option<CueSheet> = None;
+    let mut cover: Option<PathBuf> = None;
+
+    loop {
+        match CmusClient::connect(&config.connection) {
+            Ok(mut client) => loop {
+                // A cmus that stops answering is handled like a closed connection.
+                let status = client.status().map(|(mut m, errors)| {
+                    let changed = m.file != file;
+                    if changed {
+                        file = m.file.clone();
+                        sheet = if local { m.read_cue_sheet() } else { None };
+                    }
+
+                    if let Some(sheet) = &sheet {
+                        m.apply_cue(sheet);
+                    }
+
+                    // After the sheet, which gives the audio file of a CUE track.
+                    if changed {
+                        cover = m.get_cover(&config.cover);
+                    }
+
+                    (m, errors, cover.clone())
+                });
+                let lost = status.is_err();
+                on_status(status);
+
+                if lost {
+                    break;
+                }
+                thread::sleep(interval);
+            },
+            Err(e) => on_status(Err(e)),
+        }
+
+        thread::sleep(reconnect_interval);
+    }
+}
+
+/// Follow cmus and notify on the events of `events.notify`.
+pub fn run(config: &Config) {
+    // The last status and when it was received, the time between two polls includes the query
+    // and the reconnections.
+    let mut previous: Option<(Metadata, Instant)> = None;
+    let mut notification_id: Option<u32> = None;
+    // Incremented for each notification so only the latest one handles its buttons.
+    let generation = Arc::new(AtomicU64::new(0));
+    // The thread waiting for the buttons of the last notification.
+    let mut waiter: Option<JoinHandle<()>> = None;
+
+    follow(config, |status| {
+        let (current, errors, cover) = match status {
+            Ok(status) => status,
+            Err(_) => return,
+        };
+
+        let changed = previous
+            .as_ref()
+            .and_then(|(previous, at)| event::classify(previous, &current, at.elapsed()))
+            .is_some_and(|e| config.events.notify.contains(&e));
+        previous = Some((current.clone(), Instant::now()));
+
+        if !changed {
+            return;
+        }
+
+        let m = match check_errors(config, current, errors) {
+            Some(m) => m,
+            None => return,
+        };
+
+        let shown = match notify_metadata(&m, cover, config, notification_id) {
+            Ok(shown) => shown,
+            Err(e) => {
+                logging::log(&e);
+                return;
+            }
+        };
+        // A notification replaced in place keeps its id, and its buttons are still handled by the
+        // running waiter: a new one would pile up on servers keeping the notifications.
+        let replaced = shown.id.is_some() && shown.id == notification_id;
+        notification_id = shown.id;
+        if replaced && waiter.as_ref().is_some_and(|w| !w.is_finished()) {
+            return;
+        }
+
+        let current_gen = generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let generation = Arc::clone(&generation);
+        let connection = config.connection.clone();
+
+        waiter = Some(thread::spawn(move || {
+            if let Some(command) = shown.wait_for_action() {
+                if generation.load(Ordering::SeqCst) == current_gen {
+                    let _ = actions::send_command(command, &connection);
+                }
+            }
+        }));
+    });
+}
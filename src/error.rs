@@ -0,0 +1,81 @@
+use std::fmt;
+use std::io;
+use std::string::FromUtf8Error;
+
+/// Errors of the communication with cmus.
+#[derive(Debug)]
+pub enum Error {
+    /// The path of the cmus socket could not be determined.
+    NoSocketPath,
+    /// Connecting to cmus failed, usually because it is not running.
+    Connection(String, io::Error),
+    /// cmus closed the connection after the password was sent.
+    Authentication,
+    /// No password was given for the TCP server at this address.
+    PasswordRequired(String),
+    /// Reading from or writing to cmus failed.
+    Io(io::Error),
+    /// cmus did not answer in time.
+    Timeout,
+    /// cmus sent an unexpected response.
+    Protocol(String),
+    /// cmus sent invalid UTF-8.
+    Utf8(FromUtf8Error),
+    /// A numeric or boolean field has an invalid value.
+    Field {
+        name: String,
+        value: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NoSocketPath => write!(f, "Unable to determine socket path"),
+            Error::Connection(address, e) => write!(f, "Not running ({}: {})", address, e),
+            Error::Authentication => write!(f, "Authentication failed, check the password"),
+            Error::PasswordRequired(address) => {
+                write!(f, "A password is required to connect to {}", address)
+            }
+            Error::Io(e) => write!(f, "Error communicating with cmus: {}", e),
+            Error::Timeout => write!(f, "cmus is not responding"),
+            Error::Protocol(msg) => write!(f, "Unexpected response from cmus: {}", msg),
+            Error::Utf8(e) => write!(f, "Invalid UTF-8 received from cmus: {}", e),
+            Error::Field {
+                name,
+                value,
+                source,
+            } => {
+                write!(f, "Invalid value for {}: {:?} ({})", name, value, source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Connection(_, e) | Error::Io(e) => Some(e),
+            Error::Utf8(e) => Some(e),
+            Error::Field { source, .. } => Some(source.as_ref()),
+            Error::NoSocketPath
+            | Error::Authentication
+            | Error::PasswordRequired(_)
+            | Error::Timeout
+            | Error::Protocol(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<FromUtf8Error> for Error {
+    fn from(e: FromUtf8Error) -> Self {
+        Error::Utf8(e)
+    }
+}
@@ -0,0 +1,192 @@
+use std::time::Duration;
+
+use cmus_notify::{Metadata, PlaybackStatus};
+use serde::Deserialize;
+
+/// Difference between the expected and the actual position above which the position was changed
+/// by a seek.
+const SEEK_TOLERANCE: Duration = Duration::from_secs(2);
+
+/// What changed between two successive statuses of cmus.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Event {
+    TrackChanged,
+    Paused,
+    Resumed,
+    Stopped,
+    Seeked,
+    /// Volume, shuffle, repeat or any other player setting.
+    SettingsChanged,
+}
+
+/// The event between `previous` and `current`, `elapsed` apart, `None` if nothing changed.
+///
+/// When several things changed, the track and the playback status win over a seek, which wins
+/// over the settings.
+pub fn classify(previous: &Metadata, current: &Metadata, elapsed: Duration) -> Option<Event> {
+    if previous.status != current.status {
+        return match current.status {
+            PlaybackStatus::Stopped => Some(Event::Stopped),
+            PlaybackStatus::Paused => Some(Event::Paused),
+            PlaybackStatus::Playing if is_new_track(previous, current) => Some(Event::TrackChanged),
+            PlaybackStatus::Playing => Some(Event::Resumed),
+            PlaybackStatus::Unknown(_) => None,
+        };
+    }
+
+    if is_new_track(previous, current)
+        && !current.file.is_empty()
+        && current.status != PlaybackStatus::Stopped
+    {
+        return Some(Event::TrackChanged);
+    }
+
+    if previous.file == current.file && is_seek(previous, current, elapsed) {
+        return Some(Event::Seeked);
+    }
+
+    if previous.settings != current.settings {
+        return Some(Event::SettingsChanged);
+    }
+
+    None
+}
+
+/// Another file, or another song on the same stream.
+fn is_new_track(previous: &Metadata, current: &Metadata) -> bool {
+    previous.file != current.file
+        || (current.is_stream()
+            && (previous.artist != current.artist || previous.title != current.title))
+}
+
+/// The position moves by `elapsed` while playing and stays still otherwise, anything else is a
+/// seek. The position is unknown when cmus passes the status as arguments.
+pub(crate) fn is_seek(previous: &Metadata, current: &Metadata, elapsed: Duration) -> bool {
+    if previous.position.is_zero() && current.position.is_zero() {
+        return false;
+    }
+
+    let expected = match current.status {
+        PlaybackStatus::Playing => previous.position + elapsed,
+        PlaybackStatus::Paused => previous.position,
+        _ => return false,
+    };
+
+    current.position.abs_diff(expected) > SEEK_TOLERANCE
+}
+
+#[cfg(test)]
+mod test_event {
+    use super::{classify, Event, Metadata, PlaybackStatus};
+    use rstest::rstest;
+    use std::time::Duration;
+
+    fn meta(status: &str, file: &str, position: u64) -> Metadata {
+        Metadata {
+            status: PlaybackStatus::from(status),
+            file: file.to_string(),
+            position: Duration::from_secs(position),
+            ..Default::default()
+        }
+    }
+
+    #[rstest]
+    #[case::same(meta("playing", "a", 1), meta("playing", "a", 1), None)]
+    #[case::position(meta("playing", "a", 1), meta("playing", "a", 2), None)]
+    #[case::next(
+        meta("playing", "a", 9),
+        meta("playing", "b", 0),
+        Some(Event::TrackChanged)
+    )]
+    #[case::pause(meta("playing", "a", 9), meta("paused", "a", 9), Some(Event::Paused))]
+    #[case::resume(meta("paused", "a", 9), meta("playing", "a", 9), Some(Event::Resumed))]
+    #[case::stop(meta("playing", "a", 9), meta("stopped", "a", 0), Some(Event::Stopped))]
+    #[case::start(meta("stopped", "a", 0), meta("playing", "a", 0), Some(Event::Resumed))]
+    #[case::start_other(
+        meta("stopped", "a", 0),
+        meta("playing", "b", 0),
+        Some(Event::TrackChanged)
+    )]
+    #[case::paused_next(
+        meta("paused", "a", 9),
+        meta("paused", "b", 0),
+        Some(Event::TrackChanged)
+    )]
+    #[case::stopped_next(meta("stopped", "a", 0), meta("stopped", "b", 0), None)]
+    #[case::seek_forward(
+        meta("playing", "a", 10),
+        meta("playing", "a", 60),
+        Some(Event::Seeked)
+    )]
+    #[case::seek_back(meta("playing", "a", 60), meta("playing", "a", 0), Some(Event::Seeked))]
+    #[case::seek_paused(meta("paused", "a", 10), meta("paused", "a", 20), Some(Event::Seeked))]
+    #[case::unknown_position(meta("playing", "a", 0), meta("playing", "a", 0), None)]
+    fn test_classify(
+        #[case] previous: Metadata,
+        #[case] current: Metadata,
+        #[case] expected: Option<Event>,
+    ) {
+        assert_eq!(
+            classify(&previous, &current, Duration::from_secs(1)),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_classify_elapsed() {
+        let previous = meta("playing", "a", 10);
+
+        assert_eq!(
+            classify(
+                &previous,
+                &meta("playing", "a", 70),
+                Duration::from_secs(60)
+            ),
+            None
+        );
+        assert_eq!(
+            classify(
+                &previous,
+                &meta("playing", "a", 10),
+                Duration::from_secs(60)
+            ),
+            Some(Event::Seeked)
+        );
+    }
+
+    #[test]
+    fn test_classify_settings() {
+        let previous = meta("playing", "a", 10);
+        let mut current = meta("playing", "a", 11);
+        current.settings.set("vol_left", "50").unwrap();
+
+        assert_eq!(
+            classify(&previous, &current, Duration::from_secs(1)),
+            Some(Event::SettingsChanged)
+        );
+
+        current.file = String::from("b");
+        assert_eq!(
+            classify(&previous, &current, Duration::from_secs(1)),
+            Some(Event::TrackChanged)
+        );
+    }
+
+    #[test]
+    fn test_classify_stream() {
+        let stream = |title: &str| Metadata {
+            title: title.to_string(),
+            ..meta("playing", "http://radio.example/stream", 0)
+        };
+
+        assert_eq!(
+            classify(&stream("Intro"), &stream("Orgasmatron"), Duration::ZERO),
+            Some(Event::TrackChanged)
+        );
+        assert_eq!(
+            classify(&stream("Intro"), &stream("Intro"), Duration::ZERO),
+            None
+        );
+    }
+}
@@ -0,0 +1,18 @@
+//! Client library for cmus, the C* Music Player.
+//!
+//! [`CmusClient`] talks to a running cmus over its Unix socket or over TCP, queries its status
+//! as a [`Metadata`] and runs commands, like `cmus-remote` does. The [`socket`] module finds
+//! where cmus listens, the [`cover`] module finds the cover of a track and the [`cue`] module
+//! reads the CUE sheets of split albums.
+
+pub mod client;
+pub mod cover;
+pub mod cue;
+pub mod error;
+pub mod metadata;
+mod protocol;
+pub mod socket;
+
+pub use client::{CmusClient, ConnectionConfig};
+pub use error::Error;
+pub use metadata::{Metadata, PlaybackStatus, PlayerSettings};
@@ -0,0 +1,38 @@
+use std::fmt::Display;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn get_log_path() -> Option<PathBuf> {
+    let mut path = dirs::cache_dir()?;
+    path.push("cmus-notify");
+    path.push("cmus-notify.log");
+
+    Some(path)
+}
+
+/// Write the error to stderr and append it to `$XDG_CACHE_HOME/cmus-notify/cmus-notify.log`.
+///
+/// stderr alone is not enough, cmus discards the output of `status_display_program`.
+pub fn log<E: Display + ?Sized>(e: &E) {
+    eprintln!("cmus-notify: {}", e);
+
+    let path = match get_log_path() {
+        Some(p) => p,
+        None => return,
+    };
+
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+        let _ = writeln!(file, "{} {}", timestamp, e);
+    }
+}
@@ -0,0 +1,1027 @@
+//! The status of cmus and its parsing.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::cover::{self, CoverConfig};
+use crate::cue::{self, CueSheet};
+use crate::error::Error;
+
+/// The status of cmus: the current track, the playback state and the player settings.
+///
+/// Numeric fields and times are 0 and text fields empty when unknown.
+#[derive(Debug, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Metadata {
+    /// Path of the playing file, URL of a stream, or `cue:///path/album.cue/3` for a track of a
+    /// CUE sheet.
+    pub file: String,
+    /// Audio file containing the track of a CUE sheet, empty for other files or if the sheet
+    /// cannot be read.
+    pub source: String,
+    pub artist: String,
+    pub album: String,
+    pub title: String,
+    pub tracknumber: u32,
+    pub tracktotal: u32,
+    pub discnumber: u32,
+    pub disctotal: u32,
+    pub date: String,
+    /// ICY title of an internet radio, usually "Artist - Song", from the `stream` line.
+    pub stream: String,
+    /// Name of the internet radio.
+    pub station: String,
+    /// Length of the track, serialized in seconds.
+    #[serde(
+        serialize_with = "serialize_secs",
+        deserialize_with = "deserialize_secs"
+    )]
+    pub duration: Duration,
+    /// Position in the track, serialized in seconds.
+    #[serde(
+        serialize_with = "serialize_secs",
+        deserialize_with = "deserialize_secs"
+    )]
+    pub position: Duration,
+    pub status: PlaybackStatus,
+    /// Every tag reported by cmus, including the ones above.
+    pub tags: BTreeMap<String, String>,
+    pub settings: PlayerSettings,
+}
+
+/// Playback state of cmus, serialized as cmus reports it: `playing`, `paused` or `stopped`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum PlaybackStatus {
+    Playing,
+    Paused,
+    Stopped,
+    /// Any other value, empty when the status is unknown.
+    Unknown(String),
+}
+
+impl Default for PlaybackStatus {
+    fn default() -> Self {
+        PlaybackStatus::Unknown(String::new())
+    }
+}
+
+impl PlaybackStatus {
+    pub fn as_str(&self) -> &str {
+        match self {
+            PlaybackStatus::Playing => "playing",
+            PlaybackStatus::Paused => "paused",
+            PlaybackStatus::Stopped => "stopped",
+            PlaybackStatus::Unknown(status) => status,
+        }
+    }
+}
+
+impl From<&str> for PlaybackStatus {
+    fn from(status: &str) -> Self {
+        match status {
+            "playing" => PlaybackStatus::Playing,
+            "paused" => PlaybackStatus::Paused,
+            "stopped" => PlaybackStatus::Stopped,
+            status => PlaybackStatus::Unknown(String::from(status)),
+        }
+    }
+}
+
+impl fmt::Display for PlaybackStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Serialize for PlaybackStatus {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for PlaybackStatus {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(|status| PlaybackStatus::from(status.as_str()))
+    }
+}
+
+fn serialize_secs<S: Serializer>(time: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(time.as_secs())
+}
+
+fn deserialize_secs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    u64::deserialize(deserializer).map(Duration::from_secs)
+}
+
+/// Player settings, from the `set` lines of the status.
+#[derive(Debug, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlayerSettings {
+    pub aaa_mode: String,
+    /// The `continue` setting.
+    pub continue_playback: bool,
+    pub play_library: bool,
+    pub play_sorted: bool,
+    pub repeat: bool,
+    pub repeat_current: bool,
+    pub replaygain: String,
+    pub replaygain_limit: bool,
+    pub replaygain_preamp: String,
+    /// `true`/`false`, or `off`/`tracks`/`albums` since cmus 2.11.
+    pub shuffle: String,
+    pub softvol: bool,
+    pub vol_left: u8,
+    pub vol_right: u8,
+    /// Every setting reported by cmus, including the ones above.
+    pub raw: BTreeMap<String, String>,
+}
+
+impl PlayerSettings {
+    /// Set the value of a setting from a `set <name> <value>` line.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        if name.is_empty() {
+            return Ok(());
+        }
+
+        self.raw.insert(String::from(name), String::from(value));
+
+        match name {
+            "aaa_mode" => self.aaa_mode = String::from(value),
+            "continue" => self.continue_playback = parse_field(name, value)?,
+            "play_library" => self.play_library = parse_field(name, value)?,
+            "play_sorted" => self.play_sorted = parse_field(name, value)?,
+            "repeat" => self.repeat = parse_field(name, value)?,
+            "repeat_current" => self.repeat_current = parse_field(name, value)?,
+            "replaygain" => self.replaygain = String::from(value),
+            "replaygain_limit" => self.replaygain_limit = parse_field(name, value)?,
+            "replaygain_preamp" => self.replaygain_preamp = String::from(value),
+            "shuffle" => self.shuffle = String::from(value),
+            "softvol" => self.softvol = parse_field(name, value)?,
+            "vol_left" => self.vol_left = parse_field(name, value)?,
+            "vol_right" => self.vol_right = parse_field(name, value)?,
+            _ => {}
+        };
+
+        Ok(())
+    }
+}
+
+impl Metadata {
+    /// Whether an internet radio is playing: `file` is a URL.
+    pub fn is_stream(&self) -> bool {
+        self.file.contains("://") && !self.is_cue()
+    }
+
+    /// Whether a track of a CUE sheet is playing.
+    pub fn is_cue(&self) -> bool {
+        self.file.starts_with("cue://")
+    }
+
+    /// Path of the audio file on disk: the source of a CUE track, else `file`. Empty for
+    /// streams.
+    pub fn get_path(&self) -> Option<PathBuf> {
+        if self.is_stream() {
+            return None;
+        }
+
+        if !self.source.is_empty() {
+            return Some(PathBuf::from(&self.source));
+        }
+
+        match cue::parse_path(&self.file) {
+            Some((sheet, _)) => Some(sheet),
+            None if self.file.is_empty() || self.is_cue() => None,
+            None => Some(PathBuf::from(&self.file)),
+        }
+    }
+
+    /// Cover of the playing file, see [`cover::find_cover`]. Streams have no cover.
+    pub fn get_cover(&self, config: &CoverConfig) -> Option<PathBuf> {
+        cover::find_cover(&self.get_path()?, config)
+    }
+
+    /// "disc 1/2, track 3/12", empty if the track number is unknown.
+    pub fn get_track(&self) -> String {
+        if self.tracknumber == 0 {
+            return String::new();
+        }
+
+        let track = format!("track {}", with_total(self.tracknumber, self.tracktotal));
+
+        if self.discnumber > 0 {
+            format!(
+                "disc {}, {}",
+                with_total(self.discnumber, self.disctotal),
+                track
+            )
+        } else {
+            track
+        }
+    }
+
+    /// Set the value of a tag from a `tag <name> <value>` line.
+    pub fn set_tag(&mut self, tag: &str, value: &str) -> Result<(), Error> {
+        if !tag.is_empty() {
+            self.tags.insert(String::from(tag), String::from(value));
+        }
+
+        match tag {
+            "title" => self.title = String::from(value),
+            "artist" => self.artist = String::from(value),
+            "album" => self.album = String::from(value),
+            "date" => self.date = String::from(value),
+            "tracknumber" => {
+                let (number, total) = parse_number(tag, value)?;
+                self.tracknumber = number;
+                self.tracktotal = total.unwrap_or(self.tracktotal);
+            }
+            "discnumber" => {
+                let (number, total) = parse_number(tag, value)?;
+                self.discnumber = number;
+                self.disctotal = total.unwrap_or(self.disctotal);
+            }
+            "tracktotal" | "totaltracks" => self.tracktotal = parse_field(tag, value.trim())?,
+            "disctotal" | "totaldiscs" => self.disctotal = parse_field(tag, value.trim())?,
+            _ => {}
+        };
+
+        Ok(())
+    }
+
+    /// Fill the artist, title and station of a stream from its ICY metadata.
+    ///
+    /// cmus reports the station in `tag title` and the song in the `stream` line, or only the
+    /// song in `tag title`. A song of the form "Artist - Song" is split when there is no artist
+    /// tag.
+    fn set_stream_info(&mut self) {
+        if !self.is_stream() {
+            return;
+        }
+
+        let song = if self.stream.is_empty() {
+            self.title.clone()
+        } else {
+            if self.station.is_empty() {
+                self.station = std::mem::take(&mut self.title);
+            }
+            self.stream.clone()
+        };
+
+        match song.split_once(" - ") {
+            Some((artist, title)) if self.artist.is_empty() => {
+                self.artist = String::from(artist.trim());
+                self.title = String::from(title.trim());
+            }
+            _ => self.title = song,
+        }
+    }
+
+    /// Fill the source file and the missing tags of a track of a CUE sheet from the sheet on
+    /// disk, see [`Metadata::apply_cue`].
+    ///
+    /// [`parse`] does not read the disk: call this only when cmus runs on this machine.
+    pub fn resolve_cue(&mut self) {
+        if let Some(sheet) = self.read_cue_sheet() {
+            self.apply_cue(&sheet);
+        }
+    }
+
+    /// The CUE sheet of the playing track, `None` if it is not a track of a CUE sheet or the
+    /// sheet cannot be read.
+    pub fn read_cue_sheet(&self) -> Option<CueSheet> {
+        let (path, _) = cue::parse_path(&self.file)?;
+
+        CueSheet::read(&path).ok()
+    }
+
+    /// Fill the source file and the missing tags of a track of a CUE sheet from `sheet`.
+    pub fn apply_cue(&mut self, sheet: &CueSheet) {
+        let Some((_, number)) = cue::parse_path(&self.file) else {
+            return;
+        };
+        let Some(track) = sheet.track(number) else {
+            return;
+        };
+
+        self.source = track.file.to_string_lossy().into_owned();
+
+        let performer = if track.performer.is_empty() {
+            &sheet.performer
+        } else {
+            &track.performer
+        };
+        for (field, value) in [
+            (&mut self.title, &track.title),
+            (&mut self.artist, performer),
+            (&mut self.album, &sheet.title),
+            (&mut self.date, &sheet.date),
+        ] {
+            if field.is_empty() {
+                field.clone_from(value);
+            }
+        }
+
+        if self.tracknumber == 0 {
+            self.tracknumber = number;
+        }
+        if self.tracktotal == 0 {
+            self.tracktotal = sheet.tracks.len() as u32;
+        }
+        if self.duration.is_zero() {
+            self.duration = sheet.duration(number).unwrap_or_default();
+        }
+    }
+
+    /// "00:14 / 02:03", or only the duration if the position is unknown.
+    pub fn get_duration(&self) -> Option<String> {
+        if self.duration.is_zero() {
+            return None;
+        }
+
+        if !self.position.is_zero() {
+            Some(format!(
+                "{} / {}",
+                format_time(self.position),
+                format_time(self.duration)
+            ))
+        } else {
+            Some(format_time(self.duration))
+        }
+    }
+}
+
+fn parse_field<T>(name: &str, value: &str) -> Result<T, Error>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    value.parse().map_err(|source: T::Err| Error::Field {
+        name: String::from(name),
+        value: String::from(value),
+        source: Box::new(source),
+    })
+}
+
+/// Parse a track or disc number, with its optional total: "3", "03" or "3/12".
+fn parse_number(name: &str, value: &str) -> Result<(u32, Option<u32>), Error> {
+    match value.split_once('/') {
+        Some((number, total)) => {
+            let number = parse_field(name, number.trim())?;
+            let total = match total.trim() {
+                "" => None,
+                total => Some(parse_field(name, total)?),
+            };
+
+            Ok((number, total))
+        }
+        None => Ok((parse_field(name, value.trim())?, None)),
+    }
+}
+
+/// "3/12" if the total is known, "3" otherwise.
+fn with_total(number: u32, total: u32) -> String {
+    if total > 0 {
+        format!("{}/{}", number, total)
+    } else {
+        number.to_string()
+    }
+}
+
+/// Parse the response to the `status` command.
+///
+/// Invalid fields are left to their default value, their errors are returned with the metadata.
+pub fn parse(data: &str) -> (Metadata, Vec<Error>) {
+    let mut m: Metadata = Metadata::default();
+    let mut errors = Vec::new();
+
+    for line in data.lines() {
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+
+        let result = match key {
+            "status" => {
+                m.status = PlaybackStatus::from(value);
+                Ok(())
+            }
+            "file" => {
+                m.file = String::from(value);
+                Ok(())
+            }
+            "stream" => {
+                m.stream = String::from(value);
+                Ok(())
+            }
+            // The duration of a stream is unknown.
+            "duration" if value == "-1" => Ok(()),
+            "duration" => parse_field(key, value).map(|v| m.duration = Duration::from_secs(v)),
+            "position" => parse_field(key, value).map(|v| m.position = Duration::from_secs(v)),
+            "tag" => {
+                let (tag, value) = value.split_once(' ').unwrap_or((value, ""));
+                m.set_tag(tag, value)
+            }
+            "set" => {
+                let (name, value) = value.split_once(' ').unwrap_or((value, ""));
+                m.settings.set(name, value)
+            }
+            _ => Ok(()),
+        };
+
+        errors.extend(result.err());
+    }
+
+    m.set_stream_info();
+
+    (m, errors)
+}
+
+/// Build the metadata from the arguments cmus passes to `status_display_program`.
+///
+/// The arguments come as key/value pairs, e.g. `status playing file /x.flac artist Foo`.
+pub fn parse_args(args: &[String]) -> (Metadata, Vec<Error>) {
+    let mut m: Metadata = Metadata::default();
+    let mut errors = Vec::new();
+
+    for pair in args.chunks(2) {
+        let value = pair.get(1).map(String::as_str).unwrap_or_default();
+
+        let result = match pair[0].as_str() {
+            "status" => {
+                m.status = PlaybackStatus::from(value);
+                Ok(())
+            }
+            "file" | "url" => {
+                m.file = String::from(value);
+                Ok(())
+            }
+            "duration" if value == "-1" => Ok(()),
+            "duration" => {
+                parse_field("duration", value).map(|v| m.duration = Duration::from_secs(v))
+            }
+            key => m.set_tag(key, value),
+        };
+
+        errors.extend(result.err());
+    }
+
+    m.set_stream_info();
+
+    (m, errors)
+}
+
+/// Format a time as "mm:ss", or "hh:mm:ss" above an hour. Fractions of seconds are dropped.
+pub fn format_time(time: Duration) -> String {
+    let mut sec = time.as_secs();
+    let mut min = sec / 60;
+    let mut hour: u64 = 0;
+
+    sec %= 60;
+
+    if min >= 60 {
+        hour = min / 60;
+        min %= 60;
+    }
+
+    if hour != 0 {
+        format!("{:02}:{:02}:{:02}", hour, min, sec)
+    } else {
+        format!("{:02}:{:02}", min, sec)
+    }
+}
+
+#[cfg(test)]
+mod test_metadata {
+    use super::{Metadata, PlaybackStatus};
+    use rstest::rstest;
+    use std::time::Duration;
+
+    #[rstest]
+    #[case(0, 0, "")]
+    #[case(3, 0, "")]
+    #[case(0, 1, "track 1")]
+    #[case(0, 2, "track 2")]
+    #[case(1, 2, "disc 1, track 2")]
+    #[case(3, 3, "disc 3, track 3")]
+    fn test_get_track(#[case] discnumber: u32, #[case] tracknumber: u32, #[case] expected: String) {
+        let meta = Metadata {
+            tracknumber,
+            discnumber,
+            ..Default::default()
+        };
+
+        assert_eq!(meta.get_track(), expected);
+    }
+
+    #[rstest]
+    #[case(0, 0, 3, 12, "track 3/12")]
+    #[case(1, 2, 3, 12, "disc 1/2, track 3/12")]
+    #[case(1, 0, 3, 12, "disc 1, track 3/12")]
+    #[case(1, 2, 3, 0, "disc 1/2, track 3")]
+    #[case(1, 2, 0, 12, "")]
+    fn test_get_track_with_totals(
+        #[case] discnumber: u32,
+        #[case] disctotal: u32,
+        #[case] tracknumber: u32,
+        #[case] tracktotal: u32,
+        #[case] expected: String,
+    ) {
+        let meta = Metadata {
+            tracknumber,
+            tracktotal,
+            discnumber,
+            disctotal,
+            ..Default::default()
+        };
+
+        assert_eq!(meta.get_track(), expected);
+    }
+
+    #[rstest]
+    #[case(0, 0, None)]
+    #[case(0, 60, Some("01:00"))]
+    #[case(58, 0, None)]
+    #[case(58, 60, Some("00:58 / 01:00"))]
+    fn test_get_duration(
+        #[case] position: u64,
+        #[case] duration: u64,
+        #[case] expected: Option<&str>,
+    ) {
+        let meta = Metadata {
+            duration: Duration::from_secs(duration),
+            position: Duration::from_secs(position),
+            ..Default::default()
+        };
+
+        assert_eq!(meta.get_duration(), expected.map(|e| e.to_string()))
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut meta = Metadata {
+            file: "/music/song.flac".to_string(),
+            title: "Orgasmatron".to_string(),
+            status: PlaybackStatus::Paused,
+            duration: Duration::from_secs(258),
+            position: Duration::from_secs(123),
+            ..Default::default()
+        };
+        meta.settings.set("vol_left", "80").unwrap();
+
+        let json = serde_json::to_string(&meta).unwrap();
+        assert_eq!(serde_json::from_str::<Metadata>(&json).unwrap(), meta);
+
+        let partial: Metadata = serde_json::from_str(r#"{"status": "playing"}"#).unwrap();
+        assert_eq!(partial.status, PlaybackStatus::Playing);
+    }
+}
+
+#[cfg(test)]
+mod test_format_time {
+    use super::format_time;
+    use rstest::rstest;
+    use std::time::Duration;
+
+    #[rstest]
+    #[case(0, "00:00")]
+    #[case(1, "00:01")]
+    #[case(59, "00:59")]
+    #[case(60, "01:00")]
+    #[case(61, "01:01")]
+    #[case(3600, "01:00:00")]
+    fn test_format_only_seconds(#[case] sec: u64, #[case] expected: String) {
+        assert_eq!(format_time(Duration::from_secs(sec)), expected);
+    }
+
+    #[test]
+    fn test_format_fraction() {
+        assert_eq!(format_time(Duration::from_millis(61_999)), "01:01");
+    }
+}
+
+#[cfg(test)]
+mod test_parse {
+    use super::{parse, Error, Metadata, PlaybackStatus, PlayerSettings};
+    use rstest::rstest;
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    fn to_map(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let data = "status stopped
+file /music/artist/album/song.flac
+duration 258
+position 123
+tag genre Neo Classical Fusion
+tag date 1824
+tag albumartist Various Artists
+tag artist Metallideth
+tag album Rust in Puppets
+tag title Orgasmatron
+tag tracknumber 69
+tag discnumber 42";
+
+        let expected = Metadata {
+            file: "/music/artist/album/song.flac".to_string(),
+            source: String::new(),
+            artist: "Metallideth".to_string(),
+            album: "Rust in Puppets".to_string(),
+            title: "Orgasmatron".to_string(),
+            tracknumber: 69,
+            tracktotal: 0,
+            discnumber: 42,
+            disctotal: 0,
+            date: "1824".to_string(),
+            stream: String::new(),
+            station: String::new(),
+            duration: Duration::from_secs(258),
+            position: Duration::from_secs(123),
+            status: PlaybackStatus::Stopped,
+            tags: to_map(&[
+                ("genre", "Neo Classical Fusion"),
+                ("date", "1824"),
+                ("albumartist", "Various Artists"),
+                ("artist", "Metallideth"),
+                ("album", "Rust in Puppets"),
+                ("title", "Orgasmatron"),
+                ("tracknumber", "69"),
+                ("discnumber", "42"),
+            ]),
+            settings: PlayerSettings::default(),
+        };
+
+        let (m, errors) = parse(data);
+
+        assert_eq!(m, expected);
+        assert!(errors.is_empty());
+    }
+
+    #[rstest]
+    #[case::plain("tag tracknumber 3", 3, 0)]
+    #[case::leading_zero("tag tracknumber 03", 3, 0)]
+    #[case::large("tag tracknumber 1042", 1042, 0)]
+    #[case::with_total("tag tracknumber 3/12", 3, 12)]
+    #[case::zero_padded_total("tag tracknumber 01/02", 1, 2)]
+    #[case::spaces("tag tracknumber 3 / 12", 3, 12)]
+    #[case::empty_total("tag tracknumber 3/", 3, 0)]
+    #[case::total_tag("tag tracknumber 3\ntag tracktotal 12", 3, 12)]
+    #[case::total_tag_first("tag totaltracks 12\ntag tracknumber 3", 3, 12)]
+    fn test_parse_tracknumber(#[case] data: &str, #[case] number: u32, #[case] total: u32) {
+        let (m, errors) = parse(data);
+
+        assert!(errors.is_empty());
+        assert_eq!((m.tracknumber, m.tracktotal), (number, total));
+    }
+
+    #[test]
+    fn test_parse_discnumber() {
+        let (m, errors) = parse("tag discnumber 1/2\ntag tracknumber 3/12");
+
+        assert!(errors.is_empty());
+        assert_eq!((m.discnumber, m.disctotal), (1, 2));
+        assert_eq!(m.get_track(), "disc 1/2, track 3/12");
+
+        let (m, errors) = parse("tag discnumber 1\ntag disctotal 2");
+
+        assert!(errors.is_empty());
+        assert_eq!((m.discnumber, m.disctotal), (1, 2));
+    }
+
+    #[rstest]
+    #[case("tag tracknumber 3/x")]
+    #[case("tag tracknumber /12")]
+    #[case("tag discnumber -1")]
+    #[case("tag tracktotal many")]
+    fn test_parse_invalid_number(#[case] data: &str) {
+        let (_, errors) = parse(data);
+
+        assert!(matches!(&errors[..], [Error::Field { .. }]));
+    }
+
+    #[test]
+    fn test_parse_stream() {
+        let data = "status playing
+file http://radio.example/stream.mp3
+duration -1
+position 754
+stream Metallideth - Orgasmatron
+tag title Radio Example";
+
+        let (m, errors) = parse(data);
+
+        assert!(errors.is_empty());
+        assert!(m.is_stream());
+        assert_eq!(m.stream, "Metallideth - Orgasmatron");
+        assert_eq!(m.station, "Radio Example");
+        assert_eq!(m.artist, "Metallideth");
+        assert_eq!(m.title, "Orgasmatron");
+        assert_eq!(m.duration, Duration::ZERO);
+        assert_eq!(m.position, Duration::from_secs(754));
+    }
+
+    #[rstest]
+    #[case::icy_title(
+        "tag title Metallideth - Orgasmatron",
+        "Metallideth",
+        "Orgasmatron",
+        ""
+    )]
+    #[case::no_separator("stream Station ID\ntag title Radio", "", "Station ID", "Radio")]
+    #[case::artist_tag(
+        "stream Orgasmatron - Live\ntag artist Metallideth",
+        "Metallideth",
+        "Orgasmatron - Live",
+        ""
+    )]
+    #[case::several_separators("stream A - B - C", "A", "B - C", "")]
+    fn test_parse_stream_title(
+        #[case] tags: &str,
+        #[case] artist: &str,
+        #[case] title: &str,
+        #[case] station: &str,
+    ) {
+        let (m, errors) = parse(&format!("status playing\nfile https://radio/\n{}", tags));
+
+        assert!(errors.is_empty());
+        assert_eq!((m.artist.as_str(), m.title.as_str()), (artist, title));
+        assert_eq!(m.station, station);
+    }
+
+    #[test]
+    fn test_parse_cue() {
+        let dir = std::env::temp_dir().join(format!("cmus-notify-cue-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let sheet = dir.join("album.cue");
+        std::fs::write(
+            &sheet,
+            "PERFORMER \"Metallideth\"
+TITLE \"Rust in Puppets\"
+REM DATE 1986
+FILE \"album.flac\" WAVE
+  TRACK 01 AUDIO
+    TITLE \"Intro\"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE \"Orgasmatron\"
+    INDEX 01 01:30:00
+  TRACK 03 AUDIO
+    TITLE \"Outro\"
+    INDEX 01 05:45:00
+",
+        )
+        .unwrap();
+
+        let (mut m, errors) = parse(&format!(
+            "status playing\nfile cue://{}/2\ntag title Orgasmatron (Remastered)",
+            sheet.display()
+        ));
+        // Parsing does not read the sheet.
+        assert_eq!((m.source.as_str(), m.album.as_str()), ("", ""));
+
+        m.resolve_cue();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(errors.is_empty());
+        assert!(m.is_cue());
+        assert!(!m.is_stream());
+        assert_eq!(m.source, dir.join("album.flac").to_string_lossy());
+        assert_eq!(m.get_path(), Some(dir.join("album.flac")));
+        assert_eq!(m.title, "Orgasmatron (Remastered)");
+        assert_eq!(m.artist, "Metallideth");
+        assert_eq!(m.album, "Rust in Puppets");
+        assert_eq!(m.date, "1986");
+        assert_eq!((m.tracknumber, m.tracktotal), (2, 3));
+        assert_eq!(m.duration, Duration::from_secs(255));
+    }
+
+    #[test]
+    fn test_parse_cue_missing_sheet() {
+        let (mut m, errors) = parse("file cue:///nonexistent/album.cue/2\nduration 255");
+        m.resolve_cue();
+
+        assert!(errors.is_empty());
+        assert_eq!(m.source, "");
+        assert_eq!(m.get_path(), Some("/nonexistent/album.cue".into()));
+        assert_eq!(m.tracknumber, 0);
+    }
+
+    #[test]
+    fn test_parse_file_title_not_split() {
+        let (m, _) = parse("file /music/song.flac\ntag title Metallideth - Orgasmatron");
+
+        assert!(!m.is_stream());
+        assert_eq!(m.artist, "");
+        assert_eq!(m.title, "Metallideth - Orgasmatron");
+    }
+
+    #[rstest]
+    #[case("status playing", PlaybackStatus::Playing)]
+    #[case("status paused", PlaybackStatus::Paused)]
+    #[case("status stopped", PlaybackStatus::Stopped)]
+    #[case("status buffering", PlaybackStatus::Unknown(String::from("buffering")))]
+    #[case("", PlaybackStatus::Unknown(String::new()))]
+    fn test_parse_status(#[case] data: &str, #[case] expected: PlaybackStatus) {
+        let (m, _) = parse(data);
+
+        assert_eq!(m.status.as_str(), data.trim_start_matches("status "));
+        assert_eq!(m.status, expected);
+    }
+
+    #[test]
+    fn test_parse_settings() {
+        let data = "status playing
+file /music/song.flac
+tag composer Lemmy
+set aaa_mode artist
+set continue true
+set play_library false
+set play_sorted false
+set replaygain track
+set replaygain_limit true
+set replaygain_preamp 6.000000
+set repeat true
+set repeat_current false
+set shuffle tracks
+set softvol false
+set vol_left 80
+set vol_right 75
+set some_future_option yes";
+
+        let (m, errors) = parse(data);
+        assert!(errors.is_empty());
+
+        let settings = m.settings;
+        assert_eq!(settings.aaa_mode, "artist");
+        assert!(settings.continue_playback);
+        assert!(!settings.play_library);
+        assert!(!settings.play_sorted);
+        assert_eq!(settings.replaygain, "track");
+        assert!(settings.replaygain_limit);
+        assert_eq!(settings.replaygain_preamp, "6.000000");
+        assert!(settings.repeat);
+        assert!(!settings.repeat_current);
+        assert_eq!(settings.shuffle, "tracks");
+        assert!(!settings.softvol);
+        assert_eq!(settings.vol_left, 80);
+        assert_eq!(settings.vol_right, 75);
+        assert_eq!(settings.raw.len(), 14);
+        assert_eq!(settings.raw["some_future_option"], "yes");
+
+        assert_eq!(m.tags, to_map(&[("composer", "Lemmy")]));
+    }
+
+    #[test]
+    fn test_parse_invalid_settings() {
+        let (m, errors) = parse("set repeat maybe\nset vol_left 300\nset vol_right 50");
+
+        assert_eq!(m.settings.vol_right, 50);
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(&errors[0], Error::Field { name, .. } if name == "repeat"));
+        assert!(matches!(&errors[1], Error::Field { name, .. } if name == "vol_left"));
+    }
+
+    #[test]
+    fn test_parse_invalid_fields() {
+        let data = "status playing
+file /music/song.flac
+duration abc
+position 12
+tag title Orgasmatron
+tag tracknumber three
+tag comment
+set";
+
+        let (m, errors) = parse(data);
+
+        let expected = Metadata {
+            file: "/music/song.flac".to_string(),
+            title: "Orgasmatron".to_string(),
+            position: Duration::from_secs(12),
+            status: PlaybackStatus::Playing,
+            tags: to_map(&[
+                ("title", "Orgasmatron"),
+                ("tracknumber", "three"),
+                ("comment", ""),
+            ]),
+            ..Default::default()
+        };
+
+        assert_eq!(m, expected);
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(&errors[0], Error::Field { name, .. } if name == "duration"));
+        assert!(matches!(&errors[1], Error::Field { value, .. } if value == "three"));
+    }
+}
+
+#[cfg(test)]
+mod test_parse_args {
+    use super::{parse_args, Metadata, PlaybackStatus};
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_args() {
+        let args = [
+            "status",
+            "playing",
+            "file",
+            "/music/artist/album/song.flac",
+            "artist",
+            "Metallideth",
+            "albumartist",
+            "Various Artists",
+            "album",
+            "Rust in Puppets",
+            "discnumber",
+            "42",
+            "tracknumber",
+            "69",
+            "title",
+            "Orgasmatron",
+            "date",
+            "1824",
+            "duration",
+            "258",
+        ]
+        .map(String::from);
+
+        let expected = Metadata {
+            file: "/music/artist/album/song.flac".to_string(),
+            artist: "Metallideth".to_string(),
+            album: "Rust in Puppets".to_string(),
+            title: "Orgasmatron".to_string(),
+            tracknumber: 69,
+            discnumber: 42,
+            date: "1824".to_string(),
+            duration: Duration::from_secs(258),
+            position: Duration::ZERO,
+            status: PlaybackStatus::Playing,
+            ..Default::default()
+        };
+
+        let m = parse_args(&args).0;
+        assert_eq!(m.tags.len(), 7);
+        assert_eq!(m.tags["albumartist"], "Various Artists");
+        assert_eq!(
+            Metadata {
+                tags: Default::default(),
+                ..m
+            },
+            expected
+        );
+    }
+
+    #[test]
+    fn test_parse_args_url() {
+        let args = ["status", "playing", "url", "http://radio.example/stream"].map(String::from);
+
+        let expected = Metadata {
+            file: "http://radio.example/stream".to_string(),
+            status: PlaybackStatus::Playing,
+            ..Default::default()
+        };
+
+        assert_eq!(parse_args(&args).0, expected);
+    }
+
+    #[test]
+    fn test_parse_args_stream() {
+        let args = [
+            "status",
+            "playing",
+            "url",
+            "http://radio.example/stream",
+            "title",
+            "Metallideth - Orgasmatron",
+            "duration",
+            "-1",
+        ]
+        .map(String::from);
+
+        let (m, errors) = parse_args(&args);
+        assert!(errors.is_empty());
+        assert_eq!(m.artist, "Metallideth");
+        assert_eq!(m.title, "Orgasmatron");
+    }
+
+    #[test]
+    fn test_parse_args_dangling_key() {
+        let args = ["status", "stopped", "title"].map(String::from);
+
+        let expected = Metadata {
+            status: PlaybackStatus::Stopped,
+            tags: [(String::from("title"), String::new())].into(),
+            ..Default::default()
+        };
+
+        assert_eq!(parse_args(&args).0, expected);
+    }
+}
@@ -0,0 +1,596 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use zbus::blocking::connection::Builder;
+use zbus::blocking::Connection;
+use zbus::zvariant::{ObjectPath, Value};
+use zbus::{fdo, interface};
+
+use cmus_notify::{Error, Metadata, PlaybackStatus};
+
+use crate::actions;
+use crate::config::Config;
+use crate::daemon;
+use crate::event;
+
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.cmus";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+/// Sends a command to cmus.
+type Control = Arc<dyn Fn(&str) -> Result<(), Error> + Send + Sync>;
+
+#[derive(Debug, Default)]
+struct State {
+    metadata: Metadata,
+    cover: Option<PathBuf>,
+}
+
+/// `org.mpris.MediaPlayer2`, cmus has no window so nothing can be raised.
+struct Root;
+
+#[interface(name = "org.mpris.MediaPlayer2")]
+impl Root {
+    fn raise(&self) {}
+
+    fn quit(&self) {}
+
+    #[zbus(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn identity(&self) -> String {
+        String::from("cmus")
+    }
+
+    #[zbus(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    #[zbus(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+struct Player {
+    state: Arc<Mutex<State>>,
+    control: Control,
+}
+
+impl Player {
+    fn send(&self, command: &str) -> fdo::Result<()> {
+        (self.control)(command).map_err(|e| fdo::Error::Failed(e.to_string()))
+    }
+
+    fn status(&self) -> PlaybackStatus {
+        self.state.lock().unwrap().metadata.status.clone()
+    }
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    fn next(&self) -> fdo::Result<()> {
+        self.send("player-next")
+    }
+
+    fn previous(&self) -> fdo::Result<()> {
+        self.send("player-prev")
+    }
+
+    fn pause(&self) -> fdo::Result<()> {
+        // player-pause toggles, only send it when actually playing.
+        match self.status() {
+            PlaybackStatus::Playing => self.send("player-pause"),
+            _ => Ok(()),
+        }
+    }
+
+    fn play_pause(&self) -> fdo::Result<()> {
+        match self.status() {
+            PlaybackStatus::Stopped => self.send("player-play"),
+            _ => self.send("player-pause"),
+        }
+    }
+
+    fn stop(&self) -> fdo::Result<()> {
+        self.send("player-stop")
+    }
+
+    fn play(&self) -> fdo::Result<()> {
+        match self.status() {
+            PlaybackStatus::Paused => self.send("player-pause"),
+            PlaybackStatus::Stopped => self.send("player-play"),
+            _ => Ok(()),
+        }
+    }
+
+    /// Seek by `offset` microseconds, cmus only seeks by whole seconds.
+    fn seek(&self, offset: i64) -> fdo::Result<()> {
+        match offset / 1_000_000 {
+            0 => Ok(()),
+            sec => self.send(&format!("seek {:+}", sec)),
+        }
+    }
+
+    fn set_position(&self, track_id: ObjectPath<'_>, position: i64) -> fdo::Result<()> {
+        let (current, duration) = {
+            let state = self.state.lock().unwrap();
+            (
+                get_track_id(&state.metadata),
+                state.metadata.duration.as_micros() as i64,
+            )
+        };
+
+        if track_id.as_str() != current || position < 0 || position > duration {
+            return Ok(());
+        }
+
+        self.send(&format!("seek {}", position / 1_000_000))
+    }
+
+    fn open_uri(&self, _uri: &str) -> fdo::Result<()> {
+        Err(fdo::Error::NotSupported(String::from(
+            "Opening URIs is not supported",
+        )))
+    }
+
+    #[zbus(signal)]
+    async fn seeked(ctxt: &zbus::SignalContext<'_>, position: i64) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> String {
+        get_playback_status(&self.status())
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn volume(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<String, Value<'static>> {
+        let state = self.state.lock().unwrap();
+        get_mpris_metadata(&state.metadata, state.cover.as_deref())
+    }
+
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn position(&self) -> i64 {
+        self.state.lock().unwrap().metadata.position.as_micros() as i64
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+fn get_playback_status(status: &PlaybackStatus) -> String {
+    match status {
+        PlaybackStatus::Playing => String::from("Playing"),
+        PlaybackStatus::Paused => String::from("Paused"),
+        _ => String::from("Stopped"),
+    }
+}
+
+fn get_track_id(m: &Metadata) -> String {
+    if m.file.is_empty() {
+        return String::from(NO_TRACK);
+    }
+
+    let mut hasher = DefaultHasher::new();
+    m.file.hash(&mut hasher);
+
+    format!("/org/cmus/track/{:016x}", hasher.finish())
+}
+
+/// Percent-encode a path into a `file://` URL.
+fn get_file_url(path: &Path) -> String {
+    let mut url = String::from("file://");
+
+    for &b in path.to_string_lossy().as_bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                url.push(b as char)
+            }
+            _ => url.push_str(&format!("%{:02X}", b)),
+        }
+    }
+
+    url
+}
+
+fn get_mpris_metadata(m: &Metadata, cover: Option<&Path>) -> HashMap<String, Value<'static>> {
+    let mut metadata = HashMap::new();
+
+    let track_id = ObjectPath::try_from(get_track_id(m)).unwrap();
+    metadata.insert(String::from("mpris:trackid"), Value::from(track_id));
+
+    if m.file.is_empty() {
+        return metadata;
+    }
+
+    if !m.duration.is_zero() {
+        metadata.insert(
+            String::from("mpris:length"),
+            Value::from(m.duration.as_micros() as i64),
+        );
+    }
+
+    if let Some(cover) = cover {
+        metadata.insert(
+            String::from("mpris:artUrl"),
+            Value::from(get_file_url(cover)),
+        );
+    }
+
+    let url = match m.get_path() {
+        Some(path) => get_file_url(&path),
+        None => m.file.clone(),
+    };
+    metadata.insert(String::from("xesam:url"), Value::from(url));
+
+    if !m.title.is_empty() {
+        metadata.insert(String::from("xesam:title"), Value::from(m.title.clone()));
+    }
+
+    if !m.artist.is_empty() {
+        metadata.insert(
+            String::from("xesam:artist"),
+            Value::from(vec![m.artist.clone()]),
+        );
+    }
+
+    if !m.album.is_empty() {
+        metadata.insert(String::from("xesam:album"), Value::from(m.album.clone()));
+    }
+
+    if m.tracknumber > 0 {
+        metadata.insert(
+            String::from("xesam:trackNumber"),
+            Value::from(m.tracknumber as i32),
+        );
+    }
+
+    if m.discnumber > 0 {
+        metadata.insert(
+            String::from("xesam:discNumber"),
+            Value::from(m.discnumber as i32),
+        );
+    }
+
+    metadata
+}
+
+fn serve(
+    builder: Builder<'_>,
+    state: Arc<Mutex<State>>,
+    control: Control,
+) -> zbus::Result<Connection> {
+    builder
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, Root)?
+        .serve_at(OBJECT_PATH, Player { state, control })?
+        .build()
+}
+
+/// Store the new status and signal the properties that changed.
+fn update(
+    conn: &Connection,
+    state: &Mutex<State>,
+    metadata: Metadata,
+    cover: Option<PathBuf>,
+    elapsed: Duration,
+) -> zbus::Result<()> {
+    let mut changed: HashMap<&str, Value> = HashMap::new();
+
+    let seeked = {
+        let mut state = state.lock().unwrap();
+        let previous = &state.metadata;
+
+        if previous.status != metadata.status {
+            changed.insert(
+                "PlaybackStatus",
+                Value::from(get_playback_status(&metadata.status)),
+            );
+        }
+
+        let mut previous_tags = previous.clone();
+        previous_tags.position = metadata.position;
+        previous_tags.status = metadata.status.clone();
+
+        if previous_tags != metadata || state.cover != cover {
+            changed.insert(
+                "Metadata",
+                Value::from(get_mpris_metadata(&metadata, cover.as_deref())),
+            );
+        }
+
+        let seeked = previous.file == metadata.file && event::is_seek(previous, &metadata, elapsed);
+
+        *state = State { metadata, cover };
+
+        seeked.then(|| state.metadata.position.as_micros() as i64)
+    };
+
+    if !changed.is_empty() {
+        conn.emit_signal(
+            None::<()>,
+            OBJECT_PATH,
+            "org.freedesktop.DBus.Properties",
+            "PropertiesChanged",
+            &(PLAYER_INTERFACE, changed, Vec::<String>::new()),
+        )?;
+    }
+
+    if let Some(position) = seeked {
+        conn.emit_signal(
+            None::<()>,
+            OBJECT_PATH,
+            PLAYER_INTERFACE,
+            "Seeked",
+            &position,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Expose cmus as an MPRIS player on the session bus, never returns.
+pub fn run(config: &Config) -> zbus::Result<()> {
+    let state = Arc::new(Mutex::new(State::default()));
+    let connection = config.connection.clone();
+    let control: Control = Arc::new(move |command| actions::send_command(command, &connection));
+
+    let conn = serve(Builder::session()?, Arc::clone(&state), control)?;
+
+    // When the previous status was received, to tell seeks from playback.
+    let mut updated = Instant::now();
+
+    // Partial metadata is still useful to media widgets, field errors are ignored.
+    daemon::follow(config, |status| {
+        if let Ok((metadata, _errors, cover)) = status {
+            let _ = update(&conn, &state, metadata, cover, updated.elapsed());
+            updated = Instant::now();
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_mpris {
+    use super::{
+        get_file_url, get_mpris_metadata, serve, update, Control, State, BUS_NAME, OBJECT_PATH,
+        PLAYER_INTERFACE,
+    };
+    use cmus_notify::{Metadata, PlaybackStatus};
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader};
+    use std::path::{Path, PathBuf};
+    use std::process::{Child, Command, Stdio};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use zbus::blocking::connection::Builder;
+    use zbus::blocking::Proxy;
+    use zbus::zvariant::{OwnedValue, Value};
+
+    #[test]
+    fn test_get_file_url() {
+        assert_eq!(
+            get_file_url(Path::new("/music/AC/DC/Back in Black/cover #1.jpg")),
+            "file:///music/AC/DC/Back%20in%20Black/cover%20%231.jpg"
+        );
+        assert_eq!(get_file_url(Path::new("/é")), "file:///%C3%A9");
+    }
+
+    #[test]
+    fn test_get_mpris_metadata() {
+        let m = Metadata {
+            file: "/music/song.flac".to_string(),
+            artist: "Metallideth".to_string(),
+            title: "Orgasmatron".to_string(),
+            album: "Rust in Puppets".to_string(),
+            duration: Duration::from_secs(258),
+            tracknumber: 3,
+            ..Default::default()
+        };
+
+        let metadata = get_mpris_metadata(&m, Some(&PathBuf::from("/music/cover.jpg")));
+
+        assert_eq!(metadata["xesam:title"], Value::from("Orgasmatron"));
+        assert_eq!(
+            metadata["xesam:artist"],
+            Value::from(vec!["Metallideth".to_string()])
+        );
+        assert_eq!(metadata["xesam:album"], Value::from("Rust in Puppets"));
+        assert_eq!(
+            metadata["xesam:url"],
+            Value::from("file:///music/song.flac")
+        );
+        assert_eq!(metadata["xesam:trackNumber"], Value::from(3i32));
+        assert_eq!(metadata["mpris:length"], Value::from(258_000_000i64));
+        assert_eq!(
+            metadata["mpris:artUrl"],
+            Value::from("file:///music/cover.jpg")
+        );
+        assert!(!metadata.contains_key("xesam:discNumber"));
+    }
+
+    #[test]
+    fn test_get_mpris_metadata_no_track() {
+        let metadata = get_mpris_metadata(&Metadata::default(), None);
+
+        assert_eq!(metadata.len(), 1);
+        assert!(metadata.contains_key("mpris:trackid"));
+    }
+
+    /// A private session bus, killed when dropped.
+    struct Bus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl Bus {
+        fn start() -> Option<Bus> {
+            let mut daemon = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()?;
+
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take()?)
+                .read_line(&mut address)
+                .ok()?;
+
+            Some(Bus {
+                daemon,
+                address: address.trim().to_string(),
+            })
+        }
+    }
+
+    impl Drop for Bus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    #[test]
+    fn test_private_bus() {
+        let bus = match Bus::start() {
+            Some(bus) => bus,
+            None => {
+                eprintln!("dbus-daemon not available, skipping");
+                return;
+            }
+        };
+
+        let commands = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&commands);
+        let control: Control = Arc::new(move |c: &str| {
+            recorded.lock().unwrap().push(c.to_string());
+            Ok(())
+        });
+
+        let state = Arc::new(Mutex::new(State::default()));
+        let server = serve(
+            Builder::address(bus.address.as_str()).unwrap(),
+            Arc::clone(&state),
+            control,
+        )
+        .unwrap();
+
+        let m = Metadata {
+            file: "/music/song.flac".to_string(),
+            title: "Orgasmatron".to_string(),
+            status: PlaybackStatus::Paused,
+            duration: Duration::from_secs(258),
+            position: Duration::from_secs(12),
+            ..Default::default()
+        };
+        update(&server, &state, m, None, Duration::from_secs(1)).unwrap();
+
+        let client = Builder::address(bus.address.as_str())
+            .unwrap()
+            .build()
+            .unwrap();
+        let player = Proxy::new(&client, BUS_NAME, OBJECT_PATH, PLAYER_INTERFACE).unwrap();
+
+        let status: String = player.get_property("PlaybackStatus").unwrap();
+        assert_eq!(status, "Paused");
+
+        let position: i64 = player.get_property("Position").unwrap();
+        assert_eq!(position, 12_000_000);
+
+        let metadata: HashMap<String, OwnedValue> = player.get_property("Metadata").unwrap();
+        assert_eq!(
+            String::try_from(metadata["xesam:title"].try_clone().unwrap()).unwrap(),
+            "Orgasmatron"
+        );
+
+        let _: () = player.call("Play", &()).unwrap();
+        let _: () = player.call("Next", &()).unwrap();
+        let _: () = player.call("Previous", &()).unwrap();
+        let _: () = player.call("Stop", &()).unwrap();
+        let _: () = player.call("Seek", &(10_000_000i64)).unwrap();
+        let _: () = player.call("Seek", &(-5_000_000i64)).unwrap();
+        // Paused: Pause is a no-op, PlayPause toggles.
+        let _: () = player.call("Pause", &()).unwrap();
+        let _: () = player.call("PlayPause", &()).unwrap();
+
+        assert_eq!(
+            *commands.lock().unwrap(),
+            vec![
+                "player-pause",
+                "player-next",
+                "player-prev",
+                "player-stop",
+                "seek +10",
+                "seek -5",
+                "player-pause",
+            ]
+        );
+    }
+}
@@ -0,0 +1,441 @@
+use std::path::PathBuf;
+
+use cmus_notify::metadata::format_time;
+use cmus_notify::{Error, Metadata, PlaybackStatus};
+
+use crate::config::{Config, ErrorPolicy, NotificationConfig};
+use crate::logging;
+use crate::notifier::{self, Message, Notifier, NotifyError, Shown};
+
+/// Render the summary of the notification.
+pub fn get_title(m: &Metadata, config: &Config) -> String {
+    let title = config
+        .format
+        .summary(&m.status)
+        .render(|f| get_field(m, f, config));
+
+    if !title.is_empty() {
+        title
+    } else if m.is_stream() && !m.title.is_empty() {
+        m.title.clone()
+    } else if m.is_stream() && !m.station.is_empty() {
+        m.station.clone()
+    } else {
+        config.notification.default_title.clone()
+    }
+}
+
+/// Render the body of the notification.
+pub fn get_message(m: &Metadata, config: &Config) -> String {
+    config
+        .format
+        .body(&m.status)
+        .render(|f| get_field(m, f, config))
+}
+
+/// Value of a template field, empty if unknown.
+fn get_field(m: &Metadata, field: &str, config: &Config) -> String {
+    match field {
+        "file" => m.file.clone(),
+        "source" => m.source.clone(),
+        "artist" => m.artist.clone(),
+        "album" => m.album.clone(),
+        "title" => m.title.clone(),
+        "date" => m.date.clone(),
+        "station" => m.station.clone(),
+        "stream" => m.stream.clone(),
+        "tracknumber" if m.tracknumber > 0 => m.tracknumber.to_string(),
+        "tracktotal" if m.tracktotal > 0 => m.tracktotal.to_string(),
+        "discnumber" if m.discnumber > 0 => m.discnumber.to_string(),
+        "disctotal" if m.disctotal > 0 => m.disctotal.to_string(),
+        "track" => m.get_track(),
+        "position" if !m.position.is_zero() => format_time(m.position),
+        "duration" if !m.duration.is_zero() => format_time(m.duration),
+        "time" => m.get_duration().unwrap_or_default(),
+        "status" => get_status(m, config),
+        _ => {
+            if let Some(tag) = field.strip_prefix("tag:") {
+                m.tags.get(tag).cloned().unwrap_or_default()
+            } else if let Some(name) = field.strip_prefix("set:") {
+                m.settings.raw.get(name).cloned().unwrap_or_default()
+            } else {
+                String::new()
+            }
+        }
+    }
+}
+
+fn get_status(m: &Metadata, config: &Config) -> String {
+    let status = &config.format.status;
+
+    match m.status {
+        PlaybackStatus::Playing => status.playing.clone(),
+        PlaybackStatus::Paused => status.paused.clone(),
+        PlaybackStatus::Stopped => status.stopped.clone(),
+        PlaybackStatus::Unknown(_) => String::from(""),
+    }
+}
+
+/// Show a notification with every backend of `notification.backends`, replacing the
+/// notification `replaces_id` if set.
+///
+/// Playback action buttons are added when `with_actions` is set and the backend supports them.
+pub fn notify(
+    title: &str,
+    msg: &str,
+    cover: Option<PathBuf>,
+    config: &NotificationConfig,
+    replaces_id: Option<u32>,
+    with_actions: bool,
+) -> Result<Shown, NotifyError> {
+    let message = Message {
+        summary: title,
+        body: msg,
+        icon: &config.icon,
+        cover: cover.as_ref().and_then(|c| c.to_str()),
+        status: "",
+        replaces_id,
+        actions: with_actions,
+    };
+
+    show(message, config)
+}
+
+pub fn notify_metadata(
+    m: &Metadata,
+    cover: Option<PathBuf>,
+    config: &Config,
+    replaces_id: Option<u32>,
+) -> Result<Shown, NotifyError> {
+    let icon = if m.is_stream() {
+        config.stream.icon(&m.station, &m.file)
+    } else {
+        None
+    };
+    let message = Message {
+        summary: &get_title(m, config),
+        body: &get_message(m, config),
+        icon: icon.unwrap_or(&config.notification.icon),
+        cover: cover.as_ref().and_then(|c| c.to_str()),
+        status: &get_status(m, config),
+        replaces_id,
+        actions: true,
+    };
+
+    show(message, &config.notification)
+}
+
+fn show(message: Message, config: &NotificationConfig) -> Result<Shown, NotifyError> {
+    let message = Message {
+        replaces_id: message.replaces_id.filter(|_| config.replace),
+        actions: message.actions && config.actions,
+        ..message
+    };
+
+    notifier::from_config(config).show(&message)
+}
+
+/// Show or log an error depending on `errors.policy`.
+pub fn report_error(config: &Config, e: &Error) {
+    if config.errors.policy == ErrorPolicy::Log {
+        logging::log(e);
+        return;
+    }
+
+    let title = &config.notification.default_title;
+    let shown = notify(
+        title,
+        &e.to_string(),
+        None,
+        &config.notification,
+        None,
+        false,
+    );
+
+    if let Err(notify_error) = shown {
+        logging::log(e);
+        logging::log(&notify_error);
+    }
+}
+
+/// Decide what to show when some fields could not be parsed, according to `errors.policy`.
+pub fn check_errors(config: &Config, m: Metadata, errors: Vec<Error>) -> Option<Metadata> {
+    if errors.is_empty() {
+        return Some(m);
+    }
+
+    match config.errors.policy {
+        ErrorPolicy::Notify => {
+            report_error(config, &errors[0]);
+            None
+        }
+        ErrorPolicy::Log => {
+            errors.iter().for_each(logging::log);
+            None
+        }
+        ErrorPolicy::Partial => {
+            errors.iter().for_each(logging::log);
+            Some(m)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_notification {
+    use super::{check_errors, get_field, get_message, get_status, get_title};
+    use crate::config::{Config, ErrorPolicy};
+    use crate::template::Template;
+    use cmus_notify::metadata::parse;
+    use cmus_notify::{Metadata, PlaybackStatus};
+    use rstest::rstest;
+    use std::time::Duration;
+
+    #[rstest]
+    #[case::no_artist_no_title("", "", "C* Music Player")]
+    #[case::artist_only("L'artist", "", "C* Music Player")]
+    #[case::title_only("", "Le Title", "C* Music Player")]
+    #[case::title_and_artist("L'artist", "Le Title", "L'artist - Le Title")]
+    fn test_get_title(#[case] artist: String, #[case] title: String, #[case] expected: String) {
+        let meta = Metadata {
+            artist,
+            title,
+            ..Default::default()
+        };
+
+        assert_eq!(get_title(&meta, &Config::default()), expected)
+    }
+
+    #[rstest]
+    #[case::split("Metallideth", "Orgasmatron", "Radio", "Metallideth - Orgasmatron")]
+    #[case::title_only("", "Station ID", "Radio", "Station ID")]
+    #[case::station_only("", "", "Radio", "Radio")]
+    #[case::nothing("", "", "", "C* Music Player")]
+    fn test_get_title_stream(
+        #[case] artist: String,
+        #[case] title: String,
+        #[case] station: String,
+        #[case] expected: String,
+    ) {
+        let meta = Metadata {
+            file: "http://radio.example/stream".to_string(),
+            artist,
+            title,
+            station,
+            ..Default::default()
+        };
+
+        assert_eq!(get_title(&meta, &Config::default()), expected)
+    }
+
+    #[test]
+    fn test_get_message_stream() {
+        let (meta, _) = parse(
+            "status playing\nfile http://radio.example/stream\nduration -1\nstream Metallideth - Orgasmatron\ntag title Radio Example",
+        );
+
+        assert_eq!(
+            get_message(&meta, &Config::default()),
+            "Radio Example\n".to_string()
+        );
+        assert_eq!(
+            get_field(&meta, "stream", &Config::default()),
+            "Metallideth - Orgasmatron"
+        );
+    }
+
+    #[test]
+    fn test_get_message() {
+        let meta = Metadata {
+            album: "L'album".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            get_message(&meta, &Config::default()),
+            "L'album\n".to_string()
+        )
+    }
+
+    #[rstest]
+    #[case::no_track_or_disc(0, 0, "")]
+    #[case::disc_only(1, 0, "")]
+    #[case::track_only(0, 69, "track 69")]
+    #[case::track_and_disc(42, 69, "disc 42, track 69")]
+    fn test_get_message_with_track(
+        #[case] discnumber: u32,
+        #[case] tracknumber: u32,
+        #[case] expected: String,
+    ) {
+        let meta = Metadata {
+            tracknumber,
+            discnumber,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            get_message(&meta, &Config::default()),
+            format!("\n{}", expected)
+        )
+    }
+
+    #[rstest]
+    #[case::no_duration_or_position(0, 0, "")]
+    #[case::position_only(1, 0, "")]
+    #[case::duration_only(0, 69, ", 01:09")]
+    #[case::position_and_duration(42, 69, ", 00:42 / 01:09")]
+    fn test_get_message_with_duration(
+        #[case] position: u64,
+        #[case] duration: u64,
+        #[case] expected: String,
+    ) {
+        let meta = Metadata {
+            position: Duration::from_secs(position),
+            duration: Duration::from_secs(duration),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            get_message(&meta, &Config::default()),
+            format!("\n{}", expected)
+        )
+    }
+
+    #[rstest]
+    #[case("playing", "")]
+    #[case("paused", " [Paused]")]
+    #[case("stopped", " [Stopped]")]
+    #[case("whatever", "")]
+    #[case("", "")]
+    fn test_get_message_with_status(#[case] status: String, #[case] expected: String) {
+        let meta = Metadata {
+            status: PlaybackStatus::from(status.as_str()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            get_message(&meta, &Config::default()),
+            format!("{}\n", expected)
+        )
+    }
+
+    #[test]
+    fn test_get_message_full() {
+        let meta = Metadata {
+            album: "Album".to_string(),
+            tracknumber: 2,
+            discnumber: 1,
+            position: Duration::from_secs(14),
+            duration: Duration::from_secs(123),
+            status: PlaybackStatus::Stopped,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            get_message(&meta, &Config::default()),
+            String::from("Album [Stopped]\ndisc 1, track 2, 00:14 / 02:03")
+        )
+    }
+
+    #[rstest]
+    #[case("playing", "")]
+    #[case("paused", " [Paused]")]
+    #[case("stopped", " [Stopped]")]
+    #[case("invalid-status", "")]
+    #[case("", "")]
+    fn test_get_status(#[case] status: String, #[case] expected: String) {
+        let meta = Metadata {
+            status: PlaybackStatus::from(status.as_str()),
+            ..Default::default()
+        };
+
+        assert_eq!(get_status(&meta, &Config::default()), expected);
+    }
+
+    #[test]
+    fn test_get_status_custom_format() {
+        let mut config = Config::default();
+        config.format.status.playing = String::from(" >");
+        config.format.status.paused = String::from(" ||");
+
+        let meta = Metadata {
+            status: PlaybackStatus::Paused,
+            ..Default::default()
+        };
+        assert_eq!(get_status(&meta, &config), " ||");
+
+        let meta = Metadata {
+            status: PlaybackStatus::Playing,
+            ..Default::default()
+        };
+        assert_eq!(get_status(&meta, &config), " >");
+    }
+
+    #[test]
+    fn test_get_message_custom_template() {
+        let mut config = Config::default();
+        config.format.body = Template::parse("{album|upper} ({date|default:n/a})").unwrap();
+        config.format.paused.body = Some(Template::parse("{status} {position}").unwrap());
+
+        let mut meta = Metadata {
+            album: "Album".to_string(),
+            position: Duration::from_secs(14),
+            status: PlaybackStatus::Playing,
+            ..Default::default()
+        };
+        assert_eq!(get_message(&meta, &config), "ALBUM (n/a)");
+
+        meta.status = PlaybackStatus::Paused;
+        assert_eq!(get_message(&meta, &config), " [Paused] 00:14");
+    }
+
+    #[test]
+    fn test_get_title_custom_default() {
+        let mut config = Config::default();
+        config.notification.default_title = String::from("cmus");
+
+        assert_eq!(get_title(&Metadata::default(), &config), "cmus");
+    }
+
+    #[test]
+    fn test_get_field_raw() {
+        let (m, _) = parse("tag genre Thrash\nset shuffle albums");
+        let config = Config::default();
+
+        assert_eq!(get_field(&m, "tag:genre", &config), "Thrash");
+        assert_eq!(get_field(&m, "tag:comment", &config), "");
+        assert_eq!(get_field(&m, "set:shuffle", &config), "albums");
+        assert_eq!(get_field(&m, "set:repeat", &config), "");
+    }
+
+    #[test]
+    fn test_get_field_source() {
+        let m = Metadata {
+            file: "cue:///music/album.cue/2".to_string(),
+            source: "/music/album.flac".to_string(),
+            ..Default::default()
+        };
+        let config = Config::default();
+
+        assert_eq!(get_field(&m, "source", &config), "/music/album.flac");
+        assert_eq!(get_field(&Metadata::default(), "source", &config), "");
+    }
+
+    #[test]
+    fn test_check_errors_policy() {
+        let m = Metadata {
+            title: "Orgasmatron".to_string(),
+            ..Default::default()
+        };
+        let errors = || parse("duration abc").1;
+
+        let mut config = Config::default();
+        assert_eq!(
+            check_errors(&config, m.clone(), Vec::new()),
+            Some(m.clone())
+        );
+        assert_eq!(check_errors(&config, m.clone(), errors()), Some(m.clone()));
+
+        config.errors.policy = ErrorPolicy::Log;
+        assert_eq!(check_errors(&config, m.clone(), errors()), None);
+    }
+}
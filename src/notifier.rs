@@ -0,0 +1,511 @@
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use notify_rust::{Notification, Timeout};
+
+#[cfg(target_os = "linux")]
+use notify_rust::Hint;
+
+#[cfg(target_os = "linux")]
+use crate::actions;
+#[cfg(target_os = "linux")]
+use crate::capabilities;
+use crate::config::{Backend, NotificationConfig};
+use crate::logging;
+
+/// Errors of the notification backends.
+#[derive(Debug)]
+pub enum NotifyError {
+    /// The notification server failed.
+    Server(notify_rust::error::Error),
+    /// Writing the notification failed.
+    Io(io::Error),
+    /// The program of the command backend could not be run or failed.
+    Command(String),
+}
+
+impl fmt::Display for NotifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotifyError::Server(e) => write!(f, "Error showing notification: {}", e),
+            NotifyError::Io(e) => write!(f, "Error showing notification: {}", e),
+            NotifyError::Command(msg) => write!(f, "Error showing notification: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for NotifyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NotifyError::Server(e) => Some(e),
+            NotifyError::Io(e) => Some(e),
+            NotifyError::Command(_) => None,
+        }
+    }
+}
+
+impl From<notify_rust::error::Error> for NotifyError {
+    fn from(e: notify_rust::error::Error) -> Self {
+        NotifyError::Server(e)
+    }
+}
+
+impl From<io::Error> for NotifyError {
+    fn from(e: io::Error) -> Self {
+        NotifyError::Io(e)
+    }
+}
+
+/// A notification to show.
+pub struct Message<'a> {
+    pub summary: &'a str,
+    pub body: &'a str,
+    /// Name or path of the icon, used when there is no cover.
+    pub icon: &'a str,
+    /// Path of the cover of the track.
+    pub cover: Option<&'a str>,
+    /// Text of the playback status, like `{status}` in templates. Only read to adapt the
+    /// notification to the capabilities of a D-Bus server.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub status: &'a str,
+    /// Notification to update instead of showing a new one, if the backend can.
+    pub replaces_id: Option<u32>,
+    /// Add the playback action buttons, if the backend can.
+    pub actions: bool,
+}
+
+impl Message<'_> {
+    /// The cover if there is one, the icon otherwise.
+    pub fn image(&self) -> &str {
+        self.cover.unwrap_or(self.icon)
+    }
+}
+
+/// A notification that was shown.
+pub struct Shown {
+    /// Id of the notification when the server provides one.
+    pub id: Option<u32>,
+    #[cfg(target_os = "linux")]
+    handle: Option<notify_rust::NotificationHandle>,
+}
+
+impl Shown {
+    /// A notification without id nor action buttons.
+    fn none() -> Shown {
+        Shown {
+            id: None,
+            #[cfg(target_os = "linux")]
+            handle: None,
+        }
+    }
+
+    /// Block until an action button is clicked or the notification is closed.
+    ///
+    /// Returns the cmus command of the clicked button, if any.
+    pub fn wait_for_action(self) -> Option<&'static str> {
+        #[cfg(target_os = "linux")]
+        if let Some(handle) = self.handle {
+            let mut command = None;
+            handle.wait_for_action(|action| command = actions::get_command(action));
+
+            return command;
+        }
+
+        None
+    }
+}
+
+/// A way of showing notifications.
+pub trait Notifier {
+    fn show(&self, message: &Message) -> Result<Shown, NotifyError>;
+}
+
+impl<N: Notifier + ?Sized> Notifier for &N {
+    fn show(&self, message: &Message) -> Result<Shown, NotifyError> {
+        (**self).show(message)
+    }
+}
+
+/// The notifiers of `notification.backends`, in order.
+pub fn from_config(config: &NotificationConfig) -> Chain<'_> {
+    let notifiers = config
+        .backends
+        .iter()
+        .map(|backend| -> Box<dyn Notifier + '_> {
+            match backend {
+                Backend::Dbus => Box::new(DbusNotifier { config }),
+                Backend::Command => Box::new(CommandNotifier {
+                    args: &config.command,
+                }),
+                Backend::Stdout => Box::new(StreamNotifier::Stdout),
+                Backend::Stderr => Box::new(StreamNotifier::Stderr),
+                // Checked when the config is loaded.
+                Backend::File => Box::new(FileNotifier {
+                    path: config.file.as_deref().unwrap_or(Path::new("")),
+                }),
+            }
+        })
+        .collect();
+
+    Chain(notifiers)
+}
+
+/// Send each notification to several notifiers.
+///
+/// A failing notifier does not stop the next ones, its error is logged. Fails only when every
+/// notifier failed, with the first error.
+pub struct Chain<'a>(pub Vec<Box<dyn Notifier + 'a>>);
+
+impl Notifier for Chain<'_> {
+    fn show(&self, message: &Message) -> Result<Shown, NotifyError> {
+        let mut shown: Option<Shown> = None;
+        let mut errors = Vec::new();
+
+        for notifier in &self.0 {
+            match notifier.show(message) {
+                // The first notification with an id is the one that can be replaced later.
+                Ok(s) if shown.as_ref().is_none_or(|shown| shown.id.is_none()) => shown = Some(s),
+                Ok(_) => {}
+                Err(e) => errors.push(e),
+            }
+        }
+
+        let mut errors = errors.into_iter();
+        let result = match shown {
+            Some(shown) => Ok(shown),
+            None => match errors.next() {
+                Some(e) => Err(e),
+                None => Ok(Shown::none()),
+            },
+        };
+        errors.for_each(|e| logging::log(&e));
+
+        result
+    }
+}
+
+/// The notification server: D-Bus on Linux, the notification center on macOS.
+///
+/// On Linux, the notification is adapted to the capabilities of the server.
+pub struct DbusNotifier<'a> {
+    config: &'a NotificationConfig,
+}
+
+impl Notifier for DbusNotifier<'_> {
+    #[cfg(target_os = "linux")]
+    fn show(&self, message: &Message) -> Result<Shown, NotifyError> {
+        let capabilities = capabilities::get();
+        let (summary, body) = capabilities.adapt(message);
+
+        let mut notification = Notification::new();
+        notification
+            .summary(&summary)
+            .body(&body)
+            .timeout(get_timeout(self.config));
+
+        match message.cover {
+            Some(cover) if capabilities.image_path => {
+                notification.icon(message.icon).image_path(cover)
+            }
+            _ => notification.icon(message.image()),
+        };
+
+        if capabilities.persistence {
+            notification.hint(Hint::Transient(self.config.transient));
+        }
+
+        if let Some(id) = message.replaces_id {
+            notification.id(id);
+        }
+
+        let with_actions = message.actions && capabilities.actions;
+        if with_actions {
+            for (command, label) in actions::ACTIONS {
+                notification.action(command, label);
+            }
+        }
+
+        let handle = notification.show()?;
+
+        Ok(Shown {
+            id: Some(handle.id()),
+            handle: if with_actions { Some(handle) } else { None },
+        })
+    }
+
+    #[cfg(target_os = "macos")]
+    fn show(&self, message: &Message) -> Result<Shown, NotifyError> {
+        Notification::new()
+            .summary(message.summary)
+            .body(message.body)
+            .icon(message.image())
+            .timeout(get_timeout(self.config))
+            .show()?;
+
+        Ok(Shown::none())
+    }
+}
+
+fn get_timeout(config: &NotificationConfig) -> Timeout {
+    match config.timeout {
+        Some(ms) => Timeout::Milliseconds(ms),
+        None => Timeout::Default,
+    }
+}
+
+/// Run a program like `notify-send`, with `{summary}`, `{body}` and `{icon}` replaced in its
+/// arguments. The program is run directly, not through a shell.
+pub struct CommandNotifier<'a> {
+    args: &'a [String],
+}
+
+impl Notifier for CommandNotifier<'_> {
+    fn show(&self, message: &Message) -> Result<Shown, NotifyError> {
+        let args = expand_args(self.args, message);
+        let (program, args) = args
+            .split_first()
+            .ok_or_else(|| NotifyError::Command(String::from("no command to run")))?;
+
+        let status = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .status()
+            .map_err(|e| NotifyError::Command(format!("unable to run {}: {}", program, e)))?;
+
+        if !status.success() {
+            return Err(NotifyError::Command(format!(
+                "{} failed ({})",
+                program, status
+            )));
+        }
+
+        Ok(Shown::none())
+    }
+}
+
+fn expand_args(args: &[String], message: &Message) -> Vec<String> {
+    let fields = [
+        ("{summary}", message.summary),
+        ("{body}", message.body),
+        ("{icon}", message.image()),
+    ];
+
+    args.iter()
+        .map(|arg| {
+            let mut expanded = String::new();
+            let mut rest = arg.as_str();
+
+            // The values are not expanded again, a title may contain "{body}".
+            while let Some(start) = rest.find('{') {
+                expanded.push_str(&rest[..start]);
+                rest = &rest[start..];
+
+                match fields.iter().find(|(name, _)| rest.starts_with(name)) {
+                    Some((name, value)) => {
+                        expanded.push_str(value);
+                        rest = &rest[name.len()..];
+                    }
+                    None => {
+                        expanded.push('{');
+                        rest = &rest[1..];
+                    }
+                }
+            }
+            expanded.push_str(rest);
+
+            expanded
+        })
+        .collect()
+}
+
+/// Print each notification on a line of stdout or stderr.
+pub enum StreamNotifier {
+    Stdout,
+    Stderr,
+}
+
+impl Notifier for StreamNotifier {
+    fn show(&self, message: &Message) -> Result<Shown, NotifyError> {
+        let line = format_line(message);
+
+        match self {
+            StreamNotifier::Stdout => writeln!(io::stdout(), "{}", line)?,
+            StreamNotifier::Stderr => writeln!(io::stderr(), "{}", line)?,
+        }
+
+        Ok(Shown::none())
+    }
+}
+
+/// Append each notification to a file, on a line starting with its timestamp.
+pub struct FileNotifier<'a> {
+    path: &'a Path,
+}
+
+impl Notifier for FileNotifier<'_> {
+    fn show(&self, message: &Message) -> Result<Shown, NotifyError> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path)?;
+        writeln!(file, "{} {}", timestamp, format_line(message))?;
+
+        Ok(Shown::none())
+    }
+}
+
+/// The summary and the lines of the body on a single line.
+fn format_line(message: &Message) -> String {
+    std::iter::once(message.summary)
+        .chain(message.body.lines())
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+/// Keep the notifications in memory instead of showing them.
+#[cfg(test)]
+#[derive(Default)]
+pub struct Recorder {
+    /// The summary and body of each notification.
+    pub shown: std::cell::RefCell<Vec<(String, String)>>,
+    /// Id returned for each notification.
+    pub id: Option<u32>,
+}
+
+#[cfg(test)]
+impl Notifier for Recorder {
+    fn show(&self, message: &Message) -> Result<Shown, NotifyError> {
+        self.shown
+            .borrow_mut()
+            .push((message.summary.to_string(), message.body.to_string()));
+
+        Ok(Shown {
+            id: self.id,
+            ..Shown::none()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_notifier {
+    use super::{
+        expand_args, format_line, Chain, FileNotifier, Message, Notifier, NotifyError, Recorder,
+        Shown,
+    };
+    use std::fs;
+
+    const MESSAGE: Message = Message {
+        summary: "Metallideth - Orgasmatron",
+        body: "Album\ntrack 3, 00:14 / 04:18",
+        icon: "applications-multimedia",
+        cover: Some("/music/cover.jpg"),
+        status: "",
+        replaces_id: None,
+        actions: false,
+    };
+
+    struct Failing;
+
+    impl Notifier for Failing {
+        fn show(&self, _message: &Message) -> Result<Shown, NotifyError> {
+            Err(NotifyError::Command(String::from("no server")))
+        }
+    }
+
+    #[test]
+    fn test_format_line() {
+        assert_eq!(
+            format_line(&MESSAGE),
+            "Metallideth - Orgasmatron | Album | track 3, 00:14 / 04:18"
+        );
+
+        let message = Message {
+            body: "\nAlbum",
+            ..MESSAGE
+        };
+        assert_eq!(format_line(&message), "Metallideth - Orgasmatron | Album");
+    }
+
+    #[test]
+    fn test_expand_args() {
+        let args = ["notify-send", "--icon={icon}", "{summary}", "{body}"].map(String::from);
+
+        assert_eq!(
+            expand_args(&args, &MESSAGE),
+            [
+                "notify-send",
+                "--icon=/music/cover.jpg",
+                "Metallideth - Orgasmatron",
+                "Album\ntrack 3, 00:14 / 04:18"
+            ]
+        );
+
+        let message = Message {
+            summary: "{body} {icon",
+            ..MESSAGE
+        };
+        let args = ["{summary}{summary}", "{{icon}}"].map(String::from);
+        assert_eq!(
+            expand_args(&args, &message),
+            ["{body} {icon{body} {icon", "{/music/cover.jpg}"]
+        );
+    }
+
+    #[test]
+    fn test_chain_continues_after_failure() {
+        let first = Recorder::default();
+        let second = Recorder {
+            id: Some(42),
+            ..Default::default()
+        };
+        let chain = Chain(vec![Box::new(Failing), Box::new(&first), Box::new(&second)]);
+
+        let shown = chain.show(&MESSAGE).unwrap();
+        assert_eq!(shown.id, Some(42));
+        assert_eq!(first.shown.borrow().len(), 1);
+        assert_eq!(
+            second.shown.borrow()[0],
+            (MESSAGE.summary.to_string(), MESSAGE.body.to_string())
+        );
+    }
+
+    #[test]
+    fn test_chain_all_failing() {
+        let chain = Chain(vec![Box::new(Failing)]);
+
+        assert!(matches!(chain.show(&MESSAGE), Err(NotifyError::Command(_))));
+    }
+
+    #[test]
+    fn test_file() {
+        let dir = std::env::temp_dir().join(format!("cmus-notify-file-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("notifications.log");
+        let notifier = FileNotifier { path: &path };
+
+        notifier.show(&MESSAGE).unwrap();
+        notifier.show(&MESSAGE).unwrap();
+
+        let lines = fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = lines.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].ends_with(" Metallideth - Orgasmatron | Album | track 3, 00:14 / 04:18"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,476 @@
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+
+use cmus_notify::metadata::format_time;
+use cmus_notify::{socket, CmusClient, ConnectionConfig, Error, Metadata, PlaybackStatus};
+
+use crate::actions;
+use crate::config::Config;
+use crate::daemon;
+use crate::logging;
+use crate::notification::{get_message, get_title};
+
+/// Header of the i3bar protocol, the lists of blocks follow as an infinite array.
+const I3BAR_HEADER: &str = "{\"version\":1,\"click_events\":true}\n[";
+
+/// Name of the i3bar block, sent back in click events.
+const BLOCK_NAME: &str = "cmus";
+
+/// Format of the now-playing output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    /// The i3bar protocol, also spoken by swaybar. Always streamed.
+    I3bar,
+}
+
+/// The metadata with the fields status bars need, waybar's `text`, `tooltip`, `class` and
+/// `percentage` included.
+#[derive(Serialize)]
+struct Output<'a> {
+    #[serde(flatten)]
+    metadata: &'a Metadata,
+    position_time: String,
+    duration_time: String,
+    time: String,
+    cover: Option<&'a Path>,
+    text: String,
+    tooltip: String,
+    class: &'a str,
+    percentage: u32,
+}
+
+/// A block of the i3bar protocol.
+#[derive(Serialize)]
+struct Block<'a> {
+    name: &'a str,
+    full_text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<&'a str>,
+}
+
+/// A click on a block, sent by i3bar on stdin.
+#[derive(Deserialize)]
+struct ClickEvent {
+    name: Option<String>,
+    button: u32,
+}
+
+/// Parse `--format FORMAT` and `--watch`, in any order.
+///
+/// Returns `None` if the arguments are not output options, and the usage when they are invalid.
+pub fn parse_options(args: &[String]) -> Option<Result<(Format, bool), String>> {
+    match args.first()?.as_str() {
+        "--format" | "--watch" => {}
+        _ => return None,
+    }
+
+    let mut format = Format::Json;
+    let mut watch = false;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--watch" => watch = true,
+            "--format" => match args.next().map(String::as_str) {
+                Some("json") => format = Format::Json,
+                Some("i3bar") => format = Format::I3bar,
+                Some(other) => {
+                    return Some(Err(format!(
+                        "unknown format '{}', expected json or i3bar",
+                        other
+                    )));
+                }
+                None => return Some(Err(String::from("usage: cmus-notify --format json|i3bar"))),
+            },
+            other => return Some(Err(format!("unexpected argument '{}'", other))),
+        }
+    }
+
+    Some(Ok((format, watch || format == Format::I3bar)))
+}
+
+/// Render the status, an empty `Metadata` when cmus is not running.
+pub fn render(format: Format, m: &Metadata, cover: Option<&Path>, config: &Config) -> String {
+    match format {
+        Format::Json => to_json(m, cover, config),
+        Format::I3bar => to_i3bar(m, config),
+    }
+}
+
+fn to_json(m: &Metadata, cover: Option<&Path>, config: &Config) -> String {
+    // An empty text hides the waybar module while cmus is not running.
+    let (text, tooltip) = match m.status.as_str().is_empty() {
+        true => (String::new(), String::new()),
+        false => (get_title(m, config), get_message(m, config)),
+    };
+
+    let output = Output {
+        metadata: m,
+        position_time: format_time(m.position),
+        duration_time: format_time(m.duration),
+        time: m.get_duration().unwrap_or_default(),
+        cover,
+        text: escape_markup(&text),
+        tooltip: escape_markup(&tooltip),
+        class: get_class(m),
+        percentage: get_percentage(m),
+    };
+
+    serde_json::to_string(&output).unwrap()
+}
+
+/// The list of blocks, a single block colored by playback status.
+fn to_i3bar(m: &Metadata, config: &Config) -> String {
+    let colors = &config.i3bar;
+    let (full_text, color) = match &m.status {
+        PlaybackStatus::Unknown(status) if status.is_empty() => (String::new(), ""),
+        PlaybackStatus::Playing => (get_title(m, config), colors.playing.as_str()),
+        PlaybackStatus::Paused => (get_title(m, config), colors.paused.as_str()),
+        PlaybackStatus::Stopped => (get_title(m, config), colors.stopped.as_str()),
+        PlaybackStatus::Unknown(_) => (get_title(m, config), ""),
+    };
+
+    let block = Block {
+        name: BLOCK_NAME,
+        full_text,
+        color: Some(color).filter(|c| !c.is_empty()),
+    };
+
+    serde_json::to_string(&[block]).unwrap()
+}
+
+/// Map a click event read from stdin to the cmus command of the clicked button: left click
+/// toggles pause, middle click goes to the previous track and right click to the next one.
+fn get_click_command(line: &str) -> Option<&'static str> {
+    // The events are elements of an infinite array: "[" first, then separated by commas.
+    let line = line.trim().trim_start_matches(',');
+    let event: ClickEvent = serde_json::from_str(line).ok()?;
+
+    if event.name.as_deref() != Some(BLOCK_NAME) {
+        return None;
+    }
+
+    match event.button {
+        1 => Some("player-pause"),
+        2 => Some("player-prev"),
+        3 => Some("player-next"),
+        _ => None,
+    }
+}
+
+/// Send the commands of the click events read from stdin, until it is closed.
+fn handle_clicks(connection: &ConnectionConfig) {
+    for line in std::io::stdin().lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+
+        if let Some(command) = get_click_command(&line) {
+            if let Err(e) = actions::send_command(command, connection) {
+                logging::log(&e);
+            }
+        }
+    }
+}
+
+/// waybar parses `text` and `tooltip` as Pango markup.
+fn escape_markup(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn get_class(m: &Metadata) -> &str {
+    match m.status.as_str() {
+        "" => "offline",
+        status => status,
+    }
+}
+
+fn get_percentage(m: &Metadata) -> u32 {
+    if m.duration.is_zero() {
+        return 0;
+    }
+
+    (m.position.min(m.duration).as_millis() * 100 / m.duration.as_millis()) as u32
+}
+
+/// Print the status once. Returns false if cmus could not be queried.
+pub fn print(format: Format, config: &Config) -> bool {
+    let (m, ok) = match CmusClient::connect(&config.connection).and_then(|mut c| c.status()) {
+        Ok((mut m, _errors)) => {
+            if config.connection.is_local() {
+                m.resolve_cue();
+            }
+            (m, true)
+        }
+        Err(e) => {
+            logging::log(&e);
+            (Metadata::default(), false)
+        }
+    };
+
+    let cover = m.get_cover(&config.cover);
+    println!("{}", render(format, &m, cover.as_deref(), config));
+
+    ok
+}
+
+/// Print where cmus may be listening and whether it answers, the socket that is used is marked
+/// with `*`. Returns whether cmus answered.
+pub fn print_sockets(connection: &ConnectionConfig) -> bool {
+    // A configured server or socket is the only one tried.
+    if connection.server.is_some() || connection.socket.is_some() {
+        return match CmusClient::connect(connection) {
+            Ok(client) => {
+                println!("* configured: {}", client.address());
+                true
+            }
+            Err(e) => {
+                println!("  configured: {}", e);
+                false
+            }
+        };
+    }
+
+    let candidates = socket::candidates();
+    if candidates.is_empty() {
+        println!("{}", Error::NoSocketPath);
+        return false;
+    }
+
+    let mut chosen = false;
+    for candidate in candidates {
+        let state = match CmusClient::connect_unix(&candidate.path, connection) {
+            Ok(_) => String::new(),
+            Err(Error::Connection(_, e)) => format!(" ({})", e),
+            Err(e) => format!(" ({})", e),
+        };
+
+        let mark = if state.is_empty() && !chosen {
+            chosen = true;
+            '*'
+        } else {
+            ' '
+        };
+        println!(
+            "{} {}: {}{}",
+            mark,
+            candidate.source,
+            candidate.path.display(),
+            state
+        );
+    }
+
+    chosen
+}
+
+/// Print a line each time the status changes, never returns.
+///
+/// Exits when stdout is closed, i.e. when the status bar is restarted.
+pub fn watch(format: Format, config: &Config) {
+    let mut previous = String::new();
+    let mut first = true;
+
+    if format == Format::I3bar {
+        if writeln!(std::io::stdout(), "{}", I3BAR_HEADER).is_err() {
+            return;
+        }
+
+        let connection = config.connection.clone();
+        thread::spawn(move || handle_clicks(&connection));
+    }
+
+    daemon::follow(config, |status| {
+        let (m, cover) = status
+            .map(|(m, _errors, cover)| (m, cover))
+            .unwrap_or_default();
+
+        let line = render(format, &m, cover.as_deref(), config);
+        if line == previous {
+            return;
+        }
+
+        let separator = if format == Format::I3bar && !first {
+            ","
+        } else {
+            ""
+        };
+
+        let mut stdout = std::io::stdout().lock();
+        if writeln!(stdout, "{}{}", separator, line)
+            .and_then(|_| stdout.flush())
+            .is_err()
+        {
+            std::process::exit(0);
+        }
+        previous = line;
+        first = false;
+    });
+}
+
+#[cfg(test)]
+mod test_output {
+    use super::{get_click_command, get_percentage, parse_options, render, Format};
+    use crate::config::Config;
+    use crate::control::to_args;
+    use cmus_notify::metadata::parse;
+    use cmus_notify::{Metadata, PlaybackStatus};
+    use rstest::rstest;
+    use serde_json::Value;
+    use std::path::Path;
+    use std::time::Duration;
+
+    #[rstest]
+    #[case(&["--format", "json"], Format::Json, false)]
+    #[case(&["--watch"], Format::Json, true)]
+    #[case(&["--format", "json", "--watch"], Format::Json, true)]
+    #[case(&["--watch", "--format", "json"], Format::Json, true)]
+    #[case(&["--format", "i3bar"], Format::I3bar, true)]
+    fn test_parse_options(#[case] args: &[&str], #[case] format: Format, #[case] watch: bool) {
+        assert_eq!(parse_options(&to_args(args)), Some(Ok((format, watch))));
+    }
+
+    #[rstest]
+    #[case(&["--format"])]
+    #[case(&["--format", "xml"])]
+    #[case(&["--watch", "next"])]
+    fn test_parse_options_usage(#[case] args: &[&str]) {
+        assert!(matches!(parse_options(&to_args(args)), Some(Err(_))));
+    }
+
+    #[rstest]
+    #[case(&[])]
+    #[case(&["--daemon"])]
+    #[case(&["status", "playing"])]
+    fn test_not_output_options(#[case] args: &[&str]) {
+        assert_eq!(parse_options(&to_args(args)), None);
+    }
+
+    #[test]
+    fn test_json() {
+        let (m, _) = parse(
+            "status paused
+file /music/song.flac
+duration 258
+position 129
+tag artist Simon & Garfunkel
+tag title <Untitled>
+tag album Bookends
+tag tracknumber 3
+tag genre Folk
+set shuffle tracks
+set vol_left 80",
+        );
+        let cover = Path::new("/music/cover.jpg");
+
+        let json = render(Format::Json, &m, Some(cover), &Config::default());
+        let json: Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(json["file"], "/music/song.flac");
+        assert_eq!(json["artist"], "Simon & Garfunkel");
+        assert_eq!(json["status"], "paused");
+        assert_eq!(json["position"], 129);
+        assert_eq!(json["duration"], 258);
+        assert_eq!(json["tags"]["genre"], "Folk");
+        assert_eq!(json["settings"]["shuffle"], "tracks");
+        assert_eq!(json["settings"]["vol_left"], 80);
+        assert_eq!(json["position_time"], "02:09");
+        assert_eq!(json["duration_time"], "04:18");
+        assert_eq!(json["time"], "02:09 / 04:18");
+        assert_eq!(json["cover"], "/music/cover.jpg");
+        assert_eq!(json["text"], "Simon &amp; Garfunkel - &lt;Untitled&gt;");
+        assert_eq!(json["tooltip"], "Bookends [Paused]\ntrack 3, 02:09 / 04:18");
+        assert_eq!(json["class"], "paused");
+        assert_eq!(json["percentage"], 50);
+    }
+
+    #[test]
+    fn test_json_offline() {
+        let json = render(Format::Json, &Metadata::default(), None, &Config::default());
+        let json: Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(json["text"], "");
+        assert_eq!(json["class"], "offline");
+        assert_eq!(json["cover"], Value::Null);
+        assert_eq!(json["percentage"], 0);
+    }
+
+    #[rstest]
+    #[case(
+        "playing",
+        r##"[{"name":"cmus","full_text":"Metallideth - Orgasmatron","color":"#00FF00"}]"##
+    )]
+    #[case(
+        "paused",
+        r##"[{"name":"cmus","full_text":"Metallideth - Orgasmatron","color":"#FFFF00"}]"##
+    )]
+    #[case(
+        "stopped",
+        r##"[{"name":"cmus","full_text":"Metallideth - Orgasmatron","color":"#888888"}]"##
+    )]
+    #[case("", r##"[{"name":"cmus","full_text":""}]"##)]
+    fn test_i3bar(#[case] status: &str, #[case] expected: &str) {
+        let m = Metadata {
+            artist: String::from("Metallideth"),
+            title: String::from("Orgasmatron"),
+            status: PlaybackStatus::from(status),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            render(Format::I3bar, &m, None, &Config::default()),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_i3bar_no_color() {
+        let mut config = Config::default();
+        config.i3bar.playing = String::new();
+
+        let m = Metadata {
+            status: PlaybackStatus::Playing,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            render(Format::I3bar, &m, None, &config),
+            r#"[{"name":"cmus","full_text":"C* Music Player"}]"#
+        );
+    }
+
+    #[rstest]
+    #[case(r#"{"name":"cmus","button":1,"x":10,"y":5}"#, Some("player-pause"))]
+    #[case(r#",{"name":"cmus","instance":"","button":2}"#, Some("player-prev"))]
+    #[case(r#"  ,{"name":"cmus","button":3}"#, Some("player-next"))]
+    #[case(r#",{"name":"cmus","button":4}"#, None)]
+    #[case(r#",{"name":"clock","button":1}"#, None)]
+    #[case("[", None)]
+    #[case("", None)]
+    fn test_get_click_command(#[case] line: &str, #[case] expected: Option<&str>) {
+        assert_eq!(get_click_command(line), expected);
+    }
+
+    #[rstest]
+    #[case(0, 0, 0)]
+    #[case(10, 0, 0)]
+    #[case(0, 200, 0)]
+    #[case(50, 200, 25)]
+    #[case(200, 200, 100)]
+    #[case(250, 200, 100)]
+    fn test_get_percentage(#[case] position: u64, #[case] duration: u64, #[case] expected: u32) {
+        let m = Metadata {
+            position: Duration::from_secs(position),
+            duration: Duration::from_secs(duration),
+            ..Default::default()
+        };
+
+        assert_eq!(get_percentage(&m), expected);
+    }
+}
@@ -0,0 +1,266 @@
+use std::io;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::client::ConnectionConfig;
+use crate::error::Error;
+
+/// Port cmus listens on when `--listen` is given an address without a port.
+const DEFAULT_PORT: u16 = 3000;
+
+/// Connect to the cmus socket at `path`, applying the timeouts of `config`.
+pub fn connect(path: &Path, config: &ConnectionConfig) -> Result<UnixStream, Error> {
+    let sock = connect_timeout(path, Duration::from_millis(config.connect_timeout))
+        .map_err(|e| Error::Connection(path.display().to_string(), e))?;
+
+    sock.set_read_timeout(Some(Duration::from_millis(config.read_timeout)))?;
+    sock.set_write_timeout(Some(Duration::from_millis(config.write_timeout)))?;
+
+    Ok(sock)
+}
+
+/// Connect to cmus listening on `address`, `host:port` or `host` for the default port.
+pub fn connect_tcp(address: &str, config: &ConnectionConfig) -> Result<TcpStream, Error> {
+    let sock = resolve(address)
+        .and_then(|addrs| {
+            let timeout = Duration::from_millis(config.connect_timeout);
+            let mut last_error = io::Error::from(io::ErrorKind::AddrNotAvailable);
+
+            for addr in addrs {
+                match TcpStream::connect_timeout(&addr, timeout) {
+                    Ok(sock) => return Ok(sock),
+                    Err(e) => last_error = e,
+                }
+            }
+
+            Err(last_error)
+        })
+        .map_err(|e| Error::Connection(String::from(address), e))?;
+
+    sock.set_read_timeout(Some(Duration::from_millis(config.read_timeout)))?;
+    sock.set_write_timeout(Some(Duration::from_millis(config.write_timeout)))?;
+
+    Ok(sock)
+}
+
+fn resolve(address: &str) -> io::Result<Vec<SocketAddr>> {
+    match address.to_socket_addrs() {
+        Ok(addrs) => Ok(addrs.collect()),
+        Err(_) => Ok((address, DEFAULT_PORT).to_socket_addrs()?.collect()),
+    }
+}
+
+/// `UnixStream` has no `connect_timeout`, the connection is done in a separate thread.
+fn connect_timeout(path: &Path, timeout: Duration) -> io::Result<UnixStream> {
+    let (tx, rx) = mpsc::channel();
+    let path = PathBuf::from(path);
+
+    thread::spawn(move || {
+        let _ = tx.send(UnixStream::connect(path));
+    });
+
+    match rx.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(_) => Err(io::Error::from(io::ErrorKind::TimedOut)),
+    }
+}
+
+pub fn send<W: Write>(sock: &mut W, msg: &str) -> Result<(), Error> {
+    sock.write_all(msg.as_bytes())
+        .and_then(|_| sock.flush())
+        .map_err(map_timeout)
+}
+
+/// Read a response from cmus.
+///
+/// A response is a list of lines terminated by an empty line. Commands without output are
+/// answered with a single empty line, returned as an empty string.
+pub fn recv<R: Read>(sock: &mut R) -> Result<String, Error> {
+    const BUFSIZE: usize = 2048;
+    let mut buf: [u8; BUFSIZE] = [0; BUFSIZE];
+    let mut resp: Vec<u8> = Vec::new();
+
+    loop {
+        let bc = match sock.read(&mut buf) {
+            Ok(bc) => bc,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(map_timeout(e)),
+        };
+
+        if bc == 0 {
+            return match resp.is_empty() {
+                true => Err(Error::Io(io::Error::from(io::ErrorKind::UnexpectedEof))),
+                false => Err(Error::Protocol(String::from(
+                    "connection closed in the middle of a response",
+                ))),
+            };
+        }
+
+        resp.extend_from_slice(&buf[..bc]);
+
+        // The terminator can be split across two reads, so look at the whole response.
+        if resp == b"\n" || resp.ends_with(b"\n\n") {
+            break;
+        }
+    }
+
+    // Decode once, a multi-byte character can be split across two reads.
+    let mut resp = String::from_utf8(resp)?;
+    resp.truncate(resp.trim_end_matches('\n').len());
+
+    Ok(resp)
+}
+
+fn map_timeout(e: io::Error) -> Error {
+    match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => Error::Timeout,
+        _ => Error::Io(e),
+    }
+}
+
+#[cfg(test)]
+mod test_protocol {
+    use super::{connect, recv, resolve, send};
+    use crate::client::ConnectionConfig;
+    use crate::error::Error;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixListener;
+    use std::path::{Path, PathBuf};
+    use std::thread;
+    use std::time::Duration;
+
+    /// Start a fake cmus answering `status` with `chunks`, each sent in a separate write.
+    fn fake_server(name: &str, chunks: Vec<Vec<u8>>, close: bool) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cmus-notify-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("socket");
+        let listener = UnixListener::bind(&path).unwrap();
+
+        thread::spawn(move || {
+            let (mut sock, _) = listener.accept().unwrap();
+
+            let mut buf = [0u8; 7];
+            sock.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf, b"status\n");
+
+            for chunk in chunks {
+                sock.write_all(&chunk).unwrap();
+                sock.flush().unwrap();
+                thread::sleep(Duration::from_millis(20));
+            }
+
+            if !close {
+                thread::sleep(Duration::from_secs(2));
+            }
+        });
+
+        path
+    }
+
+    fn config() -> ConnectionConfig {
+        ConnectionConfig {
+            read_timeout: 500,
+            ..Default::default()
+        }
+    }
+
+    fn query(path: &Path) -> Result<String, Error> {
+        let mut sock = connect(path, &config())?;
+        send(&mut sock, "status\n")?;
+        let resp = recv(&mut sock);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        resp
+    }
+
+    #[test]
+    fn test_fragmented_utf8() {
+        let data = "status playing\ntag artist Motörhead\ntag title 夜に駆ける\n\n".as_bytes();
+        // Split inside "ö" and inside "夜".
+        let o = data.iter().position(|&b| b == 0xc3).unwrap() + 1;
+        let yoru = data.iter().position(|&b| b == 0xe5).unwrap() + 2;
+        let chunks = vec![
+            data[..o].to_vec(),
+            data[o..yoru].to_vec(),
+            data[yoru..].to_vec(),
+        ];
+
+        let path = fake_server("utf8", chunks, false);
+
+        assert_eq!(
+            query(&path).unwrap(),
+            "status playing\ntag artist Motörhead\ntag title 夜に駆ける"
+        );
+    }
+
+    #[test]
+    fn test_terminator_across_chunks() {
+        let chunks = vec![b"status paused\n".to_vec(), b"\n".to_vec()];
+        let path = fake_server("terminator", chunks, false);
+
+        assert_eq!(query(&path).unwrap(), "status paused");
+    }
+
+    #[test]
+    fn test_empty_response() {
+        let path = fake_server("empty", vec![b"\n".to_vec()], false);
+
+        assert_eq!(query(&path).unwrap(), "");
+    }
+
+    #[test]
+    fn test_short_read_timeout() {
+        let path = fake_server("timeout", vec![b"status playing\n".to_vec()], false);
+
+        assert!(matches!(query(&path), Err(Error::Timeout)));
+    }
+
+    #[test]
+    fn test_eof_mid_response() {
+        let path = fake_server("eof", vec![b"status playing\n".to_vec()], true);
+
+        assert!(matches!(query(&path), Err(Error::Protocol(_))));
+    }
+
+    #[test]
+    fn test_eof() {
+        let path = fake_server("closed", Vec::new(), true);
+
+        assert!(matches!(query(&path), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let path = fake_server("invalid", vec![b"tag title \xff\n\n".to_vec()], false);
+
+        assert!(matches!(query(&path), Err(Error::Utf8(_))));
+    }
+
+    #[test]
+    fn test_resolve_default_port() {
+        let addrs = resolve("127.0.0.1").unwrap();
+        assert_eq!(addrs[0].port(), 3000);
+
+        let addrs = resolve("127.0.0.1:3001").unwrap();
+        assert_eq!(addrs[0].port(), 3001);
+    }
+
+    #[test]
+    fn test_connection_refused() {
+        let path = std::env::temp_dir().join("cmus-notify-nonexistent-socket");
+
+        assert!(matches!(
+            connect(&path, &config()),
+            Err(Error::Connection(_, _))
+        ));
+    }
+}
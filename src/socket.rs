@@ -0,0 +1,241 @@
+//! Discovery of the socket of a running cmus.
+
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+/// Where a socket path comes from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    /// `$CMUS_SOCKET`, also used by cmus and `cmus-remote`.
+    Env,
+    /// `--listen` on the command line of a running cmus.
+    Listen,
+    /// `$XDG_RUNTIME_DIR/cmus-socket`.
+    RuntimeDir,
+    /// `socket` in the cmus config dir, when there is no runtime dir.
+    ConfigDir,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Env => write!(f, "CMUS_SOCKET"),
+            Source::Listen => write!(f, "cmus --listen"),
+            Source::RuntimeDir => write!(f, "XDG_RUNTIME_DIR"),
+            Source::ConfigDir => write!(f, "cmus config dir"),
+        }
+    }
+}
+
+/// A path where cmus may be listening.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub path: PathBuf,
+    pub source: Source,
+}
+
+/// The paths where a running cmus may be listening, in order of precedence.
+///
+/// cmus puts its socket at `$CMUS_SOCKET`, else at `$XDG_RUNTIME_DIR/cmus-socket`, else at
+/// `socket` in its config dir: `$CMUS_HOME`, `~/.cmus` if it exists, or
+/// `$XDG_CONFIG_HOME/cmus`. A socket given with `--listen` to a running cmus comes right after
+/// `$CMUS_SOCKET`. Only the first one of the runtime and config dirs is used, like cmus does.
+pub fn candidates() -> Vec<Candidate> {
+    candidates_from(
+        |name| env::var_os(name),
+        dirs::home_dir(),
+        &listen_addresses(),
+    )
+}
+
+/// `var` returns the value of an environment variable, `listen` the addresses given to
+/// `--listen`.
+fn candidates_from<F>(var: F, home: Option<PathBuf>, listen: &[String]) -> Vec<Candidate>
+where
+    F: Fn(&str) -> Option<OsString>,
+{
+    let var = |name: &str| var(name).filter(|v| !v.is_empty()).map(PathBuf::from);
+    let mut candidates = Vec::new();
+
+    if let Some(path) = var("CMUS_SOCKET") {
+        candidates.push(Candidate {
+            path,
+            source: Source::Env,
+        });
+    }
+
+    // Anything else than a path is a TCP address.
+    for address in listen.iter().filter(|a| a.contains('/')) {
+        candidates.push(Candidate {
+            path: PathBuf::from(address),
+            source: Source::Listen,
+        });
+    }
+
+    if let Some(mut path) = var("XDG_RUNTIME_DIR") {
+        path.push("cmus-socket");
+        candidates.push(Candidate {
+            path,
+            source: Source::RuntimeDir,
+        });
+    } else if let Some(dir) = get_config_dir(var("CMUS_HOME"), var("XDG_CONFIG_HOME"), home) {
+        let path = dir.join("socket");
+        candidates.push(Candidate {
+            path,
+            source: Source::ConfigDir,
+        });
+    }
+
+    candidates
+}
+
+/// The config dir of cmus, see `cmus_init()` in the sources of cmus.
+fn get_config_dir(
+    cmus_home: Option<PathBuf>,
+    config_home: Option<PathBuf>,
+    home: Option<PathBuf>,
+) -> Option<PathBuf> {
+    if cmus_home.is_some() {
+        return cmus_home;
+    }
+
+    let home = home?;
+    let legacy = home.join(".cmus");
+    if legacy.is_dir() {
+        return Some(legacy);
+    }
+
+    let mut path = config_home.unwrap_or_else(|| home.join(".config"));
+    path.push("cmus");
+
+    Some(path)
+}
+
+/// The addresses given to `--listen` on the command line of the running cmus processes.
+fn listen_addresses() -> Vec<String> {
+    let entries = match fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().as_bytes().iter().all(u8::is_ascii_digit))
+        .filter_map(|e| fs::read(e.path().join("cmdline")).ok())
+        .filter_map(|cmdline| parse_listen(&cmdline))
+        .collect()
+}
+
+/// The address given to `--listen` in a command line, if it runs cmus. The arguments are
+/// separated by NUL bytes.
+fn parse_listen(cmdline: &[u8]) -> Option<String> {
+    let args: Vec<&[u8]> = cmdline.split(|&b| b == 0).collect();
+
+    let program = Path::new(OsStr::from_bytes(args.first()?)).file_name()?;
+    if program != "cmus" {
+        return None;
+    }
+
+    args.windows(2)
+        .find(|w| w[0] == b"--listen")
+        .map(|w| String::from_utf8_lossy(w[1]).into_owned())
+}
+
+#[cfg(test)]
+mod test_socket {
+    use super::{candidates_from, parse_listen, Candidate, Source};
+    use std::ffi::OsString;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn candidate(path: &str, source: Source) -> Candidate {
+        Candidate {
+            path: PathBuf::from(path),
+            source,
+        }
+    }
+
+    fn env(vars: &'static [(&str, &str)]) -> impl Fn(&str) -> Option<OsString> {
+        move |name| {
+            vars.iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, v)| OsString::from(v))
+        }
+    }
+
+    #[test]
+    fn test_precedence() {
+        let var = env(&[
+            ("CMUS_SOCKET", "/tmp/cmus.sock"),
+            ("XDG_RUNTIME_DIR", "/run/user/1000"),
+            ("CMUS_HOME", "/home/me/cmus"),
+        ]);
+        let listen = [String::from("/tmp/listen.sock"), String::from("0.0.0.0")];
+
+        assert_eq!(
+            candidates_from(var, Some(PathBuf::from("/home/me")), &listen),
+            [
+                candidate("/tmp/cmus.sock", Source::Env),
+                candidate("/tmp/listen.sock", Source::Listen),
+                candidate("/run/user/1000/cmus-socket", Source::RuntimeDir),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_config_dir() {
+        let home = Some(PathBuf::from("/nonexistent/home"));
+
+        assert_eq!(
+            candidates_from(env(&[("CMUS_HOME", "/opt/cmus")]), home.clone(), &[]),
+            [candidate("/opt/cmus/socket", Source::ConfigDir)]
+        );
+        assert_eq!(
+            candidates_from(env(&[("XDG_CONFIG_HOME", "/etc/me")]), home.clone(), &[]),
+            [candidate("/etc/me/cmus/socket", Source::ConfigDir)]
+        );
+        assert_eq!(
+            candidates_from(env(&[("XDG_RUNTIME_DIR", "")]), home, &[]),
+            [candidate(
+                "/nonexistent/home/.config/cmus/socket",
+                Source::ConfigDir
+            )]
+        );
+        assert!(candidates_from(env(&[]), None, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_legacy_config_dir() {
+        let home = std::env::temp_dir().join(format!("cmus-notify-home-{}", std::process::id()));
+        fs::create_dir_all(home.join(".cmus")).unwrap();
+
+        assert_eq!(
+            candidates_from(env(&[]), Some(home.clone()), &[]),
+            [Candidate {
+                path: home.join(".cmus/socket"),
+                source: Source::ConfigDir
+            }]
+        );
+
+        fs::remove_dir_all(&home).unwrap();
+    }
+
+    #[test]
+    fn test_parse_listen() {
+        assert_eq!(
+            parse_listen(b"/usr/bin/cmus\0--listen\0/tmp/cmus.sock\0"),
+            Some(String::from("/tmp/cmus.sock"))
+        );
+        assert_eq!(
+            parse_listen(b"cmus\0--listen\0localhost:3000\0"),
+            Some(String::from("localhost:3000"))
+        );
+        assert_eq!(parse_listen(b"cmus\0--plugins\0"), None);
+        assert_eq!(parse_listen(b"vim\0--listen\0/tmp/cmus.sock\0"), None);
+        assert_eq!(parse_listen(b""), None);
+    }
+}
@@ -0,0 +1,182 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use cmus_notify::Metadata;
+use serde::{Deserialize, Serialize};
+
+/// Directory holding the state kept between two invocations.
+///
+/// Uses `$XDG_RUNTIME_DIR` so the state does not survive a reboot, with the cache dir as fallback.
+fn get_state_dir() -> Option<PathBuf> {
+    let mut path = dirs::runtime_dir().or_else(dirs::cache_dir)?;
+    path.push("cmus-notify");
+
+    Some(path)
+}
+
+fn get_notification_id_path() -> Option<PathBuf> {
+    let mut path = get_state_dir()?;
+    path.push("notification-id");
+
+    Some(path)
+}
+
+/// Id of the last notification shown, if any.
+pub fn load_notification_id() -> Option<u32> {
+    read_id(&get_notification_id_path()?)
+}
+
+/// Remember the id of the last notification, errors are ignored.
+pub fn save_notification_id(id: u32) {
+    if let Some(path) = get_notification_id_path() {
+        write_id(&path, id);
+    }
+}
+
+fn get_action_owner_path() -> Option<PathBuf> {
+    let mut path = get_state_dir()?;
+    path.push("action-owner");
+
+    Some(path)
+}
+
+/// Pid of the process handling the action buttons of the current notification.
+pub fn load_action_owner() -> Option<u32> {
+    read_id(&get_action_owner_path()?)
+}
+
+pub fn save_action_owner(pid: u32) {
+    if let Some(path) = get_action_owner_path() {
+        write_id(&path, pid);
+    }
+}
+
+/// Forget the owner when it exits, so a process reusing its pid is never taken for it.
+pub fn remove_action_owner() {
+    if let Some(path) = get_action_owner_path() {
+        let _ = fs::remove_file(path);
+    }
+}
+
+fn get_metadata_path() -> Option<PathBuf> {
+    let mut path = get_state_dir()?;
+    path.push("metadata.json");
+
+    Some(path)
+}
+
+/// The status seen by the previous invocation and when it was saved.
+#[derive(Serialize, Deserialize)]
+struct Snapshot<M> {
+    /// Milliseconds since the Unix epoch.
+    saved_at: u64,
+    metadata: M,
+}
+
+/// Status seen by the previous invocation and the time elapsed since, if any.
+pub fn load_metadata() -> Option<(Metadata, Duration)> {
+    read_metadata(&get_metadata_path()?, SystemTime::now())
+}
+
+/// Remember the status for the next invocation, errors are ignored.
+pub fn save_metadata(m: &Metadata) {
+    if let Some(path) = get_metadata_path() {
+        write_metadata(&path, m, SystemTime::now());
+    }
+}
+
+fn read_metadata(path: &Path, now: SystemTime) -> Option<(Metadata, Duration)> {
+    let snapshot: Snapshot<Metadata> = serde_json::from_slice(&fs::read(path).ok()?).ok()?;
+    let saved_at = UNIX_EPOCH + Duration::from_millis(snapshot.saved_at);
+
+    Some((
+        snapshot.metadata,
+        now.duration_since(saved_at).unwrap_or_default(),
+    ))
+}
+
+fn write_metadata(path: &Path, m: &Metadata, now: SystemTime) {
+    let snapshot = Snapshot {
+        saved_at: now
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64),
+        metadata: m,
+    };
+    let data = match serde_json::to_vec(&snapshot) {
+        Ok(data) => data,
+        Err(_) => return,
+    };
+
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+
+    // Written aside then renamed, so a concurrent invocation never reads half a file.
+    let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+    if fs::write(&tmp, data).is_ok() && fs::rename(&tmp, path).is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+}
+
+fn read_id(path: &Path) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+fn write_id(path: &Path, id: u32) {
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+
+    let _ = fs::write(path, id.to_string());
+}
+
+#[cfg(test)]
+mod test_state {
+    use super::{read_id, read_metadata, write_id, write_metadata};
+    use cmus_notify::{Metadata, PlaybackStatus};
+    use std::fs;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_notification_id() {
+        let dir = std::env::temp_dir().join(format!("cmus-notify-state-{}", std::process::id()));
+        let path = dir.join("notification-id");
+
+        assert_eq!(read_id(&path), None);
+
+        write_id(&path, 42);
+        assert_eq!(read_id(&path), Some(42));
+
+        fs::write(&path, "garbage").unwrap();
+        assert_eq!(read_id(&path), None);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_metadata() {
+        let dir = std::env::temp_dir().join(format!("cmus-notify-metadata-{}", std::process::id()));
+        let path = dir.join("metadata.json");
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        assert_eq!(read_metadata(&path, now), None);
+
+        let m = Metadata {
+            file: String::from("/music/song.flac"),
+            status: PlaybackStatus::Playing,
+            position: Duration::from_secs(42),
+            ..Default::default()
+        };
+        write_metadata(&path, &m, now);
+        assert_eq!(
+            read_metadata(&path, now + Duration::from_secs(5)),
+            Some((m, Duration::from_secs(5)))
+        );
+
+        fs::write(&path, "garbage").unwrap();
+        assert_eq!(read_metadata(&path, now), None);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,315 @@
+use std::fmt;
+
+use serde::Deserialize;
+
+/// Fields available in templates.
+pub const FIELDS: &[&str] = &[
+    "file",
+    "source",
+    "artist",
+    "album",
+    "title",
+    "date",
+    "station",
+    "stream",
+    "tracknumber",
+    "tracktotal",
+    "discnumber",
+    "disctotal",
+    "track",
+    "position",
+    "duration",
+    "time",
+    "status",
+];
+
+/// A parsed notification template.
+///
+/// - `{field}` is replaced by the value of the field. `{tag:name}` and `{set:name}` give the raw
+///   value of any tag or player setting reported by cmus.
+/// - `{field|filter|filter:arg}` applies filters to the value: `upper`, `lower`,
+///   `truncate:N` and `default:text`.
+/// - `{?field}...{/field}` is only rendered if the field is not empty.
+/// - `{!field}...{/field}` is only rendered if the field is empty.
+/// - `{{` and `}}` are literal braces.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Field(String, Vec<Filter>),
+    Cond(String, bool, Vec<Node>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+    Upper,
+    Lower,
+    Truncate(usize),
+    Default(String),
+}
+
+/// A block being parsed: the open conditional, if any, and its nodes.
+type Block = (Option<(String, bool)>, Vec<Node>);
+
+#[derive(Debug, PartialEq)]
+pub struct TemplateError(String);
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Template, TemplateError> {
+        // Stack of the open conditionals, the first entry is the root.
+        let mut stack: Vec<Block> = vec![(None, Vec::new())];
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '}' => return Err(TemplateError(String::from("unmatched '}', use '}}'"))),
+                '{' => {
+                    let mut tag = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => tag.push(c),
+                            None => {
+                                return Err(TemplateError(format!("unclosed '{{{}'", tag)));
+                            }
+                        }
+                    }
+
+                    let nodes = &mut stack.last_mut().unwrap().1;
+                    if !text.is_empty() {
+                        nodes.push(Node::Text(std::mem::take(&mut text)));
+                    }
+
+                    if let Some(name) = tag.strip_prefix('?') {
+                        stack.push((Some((check_field(name)?, false)), Vec::new()));
+                    } else if let Some(name) = tag.strip_prefix('!') {
+                        stack.push((Some((check_field(name)?, true)), Vec::new()));
+                    } else if let Some(name) = tag.strip_prefix('/') {
+                        match stack.pop() {
+                            Some((Some((open, negate)), children)) if open == name => {
+                                stack
+                                    .last_mut()
+                                    .unwrap()
+                                    .1
+                                    .push(Node::Cond(open, negate, children));
+                            }
+                            Some((Some((open, _)), _)) => {
+                                return Err(TemplateError(format!(
+                                    "'{{/{}}}' closes '{{?{}}}'",
+                                    name, open
+                                )));
+                            }
+                            _ => {
+                                return Err(TemplateError(format!(
+                                    "'{{/{}}}' has no matching opening tag",
+                                    name
+                                )));
+                            }
+                        }
+                    } else {
+                        let mut parts = tag.split('|');
+                        let name = check_field(parts.next().unwrap_or_default())?;
+                        let filters = parts.map(parse_filter).collect::<Result<_, _>>()?;
+                        nodes.push(Node::Field(name, filters));
+                    }
+                }
+                c => text.push(c),
+            }
+        }
+
+        if !text.is_empty() {
+            stack.last_mut().unwrap().1.push(Node::Text(text));
+        }
+
+        match stack.pop() {
+            Some((None, nodes)) => Ok(Template { nodes }),
+            Some((Some((name, _)), _)) => {
+                Err(TemplateError(format!("'{{?{}}}' is never closed", name)))
+            }
+            None => unreachable!(),
+        }
+    }
+
+    /// Render the template, `get` returns the value of a field.
+    pub fn render<F: Fn(&str) -> String>(&self, get: F) -> String {
+        let mut out = String::new();
+        render_nodes(&self.nodes, &get, &mut out);
+
+        out
+    }
+}
+
+impl TryFrom<String> for Template {
+    type Error = TemplateError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Template::parse(&value)
+    }
+}
+
+fn check_field(name: &str) -> Result<String, TemplateError> {
+    let name = name.trim();
+
+    let prefixed = ["tag:", "set:"]
+        .iter()
+        .any(|p| name.strip_prefix(p).is_some_and(|n| !n.is_empty()));
+
+    if FIELDS.contains(&name) || prefixed {
+        Ok(String::from(name))
+    } else {
+        Err(TemplateError(format!(
+            "unknown field '{}', expected tag:<name>, set:<name> or one of: {}",
+            name,
+            FIELDS.join(", ")
+        )))
+    }
+}
+
+fn parse_filter(filter: &str) -> Result<Filter, TemplateError> {
+    let (name, arg) = match filter.split_once(':') {
+        Some((name, arg)) => (name.trim(), Some(arg)),
+        None => (filter.trim(), None),
+    };
+
+    match (name, arg) {
+        ("upper", None) => Ok(Filter::Upper),
+        ("lower", None) => Ok(Filter::Lower),
+        ("truncate", Some(n)) => match n.trim().parse() {
+            Ok(n) if n > 0 => Ok(Filter::Truncate(n)),
+            _ => Err(TemplateError(format!(
+                "invalid length for 'truncate': '{}'",
+                n
+            ))),
+        },
+        ("default", Some(text)) => Ok(Filter::Default(String::from(text))),
+        ("truncate", None) | ("default", None) => Err(TemplateError(format!(
+            "filter '{}' requires an argument, e.g. '{}:...'",
+            name, name
+        ))),
+        _ => Err(TemplateError(format!(
+            "unknown filter '{}', expected one of: upper, lower, truncate:N, default:text",
+            filter
+        ))),
+    }
+}
+
+fn render_nodes<F: Fn(&str) -> String>(nodes: &[Node], get: &F, out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Field(name, filters) => {
+                let value = filters.iter().fold(get(name), apply_filter);
+                out.push_str(&value);
+            }
+            Node::Cond(name, negate, children) => {
+                if get(name).is_empty() == *negate {
+                    render_nodes(children, get, out);
+                }
+            }
+        }
+    }
+}
+
+fn apply_filter(value: String, filter: &Filter) -> String {
+    match filter {
+        Filter::Upper => value.to_uppercase(),
+        Filter::Lower => value.to_lowercase(),
+        Filter::Truncate(n) => {
+            if value.chars().count() > *n {
+                let mut value: String = value.chars().take(n - 1).collect();
+                value.push('…');
+                value
+            } else {
+                value
+            }
+        }
+        Filter::Default(default) => {
+            if value.is_empty() {
+                default.clone()
+            } else {
+                value
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_template {
+    use super::Template;
+    use rstest::rstest;
+
+    fn get(name: &str) -> String {
+        match name {
+            "artist" => String::from("Metallideth"),
+            "title" => String::from("Orgasmatron"),
+            "tag:genre" => String::from("Thrash Metal"),
+            _ => String::new(),
+        }
+    }
+
+    #[rstest]
+    #[case::text("just text", "just text")]
+    #[case::field("{artist} - {title}", "Metallideth - Orgasmatron")]
+    #[case::escape("{{{artist}}}", "{Metallideth}")]
+    #[case::upper("{artist|upper}", "METALLIDETH")]
+    #[case::lower("{artist|lower}", "metallideth")]
+    #[case::truncate("{title|truncate:5}", "Orga…")]
+    #[case::truncate_short("{title|truncate:20}", "Orgasmatron")]
+    #[case::default("{album|default:Unknown}", "Unknown")]
+    #[case::default_not_empty("{artist|default:Unknown}", "Metallideth")]
+    #[case::chained("{album|default:unknown|upper}", "UNKNOWN")]
+    #[case::cond_set("{?artist}by {artist}{/artist}", "by Metallideth")]
+    #[case::cond_empty("{?album}on {album}{/album}", "")]
+    #[case::negated("{!album}no album{/album}", "no album")]
+    #[case::nested(
+        "{?artist}{?title}{artist} - {title}{/title}{/artist}",
+        "Metallideth - Orgasmatron"
+    )]
+    #[case::nested_empty("{?artist}{?album}{artist} - {album}{/album}{/artist}", "")]
+    #[case::tag("{?tag:genre}({tag:genre|lower}){/tag:genre}", "(thrash metal)")]
+    #[case::missing_setting("{set:shuffle|default:off}", "off")]
+    fn test_render(#[case] template: &str, #[case] expected: &str) {
+        let template = Template::parse(template).unwrap();
+
+        assert_eq!(template.render(get), expected);
+    }
+
+    #[rstest]
+    #[case::unknown_field("{foo}")]
+    #[case::empty_tag_name("{tag:}")]
+    #[case::unknown_prefix("{foo:bar}")]
+    #[case::unknown_filter("{artist|bold}")]
+    #[case::bad_truncate("{artist|truncate:abc}")]
+    #[case::zero_truncate("{artist|truncate:0}")]
+    #[case::missing_arg("{artist|default}")]
+    #[case::unclosed_tag("{artist")]
+    #[case::unmatched_brace("artist}")]
+    #[case::unclosed_cond("{?artist}")]
+    #[case::mismatched_close("{?artist}{/title}")]
+    #[case::close_without_open("{/artist}")]
+    fn test_parse_error(#[case] template: &str) {
+        assert!(Template::parse(template).is_err());
+    }
+}